@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::env;
 use std::io::{stdin, Read};
 
 #[derive(Debug, Hash, PartialEq, Eq, Copy, Clone)]
@@ -7,73 +8,355 @@ struct Location {
     y: u32,
 }
 
-fn solve(coordinates: &[Location]) -> (u32, u32) {
-    let min_x = coordinates.iter().fold(0, |acc, c| c.x.min(acc));
-    let min_y = coordinates.iter().fold(0, |acc, c| c.y.min(acc));
-    let max_x = coordinates.iter().fold(0, |acc, c| c.x.max(acc));
-    let max_y = coordinates.iter().fold(0, |acc, c| c.y.max(acc));
-
-    // Determine the closest coordinate for each location
-    let mut coordinate_locations: HashMap<Location, Vec<Location>> = HashMap::new();
-    let mut safe_region_count = 0;
-    for y in min_y..=max_y {
-        for x in min_x..=max_x {
-            // cds = coordinate distances (from location)
-            let mut cds_hm = HashMap::new();
-            let mut total_distance = 0;
-            for &coordinate in coordinates.iter() {
-                let distance =
-                    (coordinate.x as i32 - x as i32).abs() + (coordinate.y as i32 - y as i32).abs();
-                cds_hm.insert(coordinate, distance);
-                total_distance += distance;
-            }
-            let mut cds_vec: Vec<(Location, i32)> = cds_hm.drain().collect();
-            cds_vec.sort_by(|a, b| a.1.cmp(&b.1));
-            if cds_vec[0].1 < cds_vec[1].1 {
-                let cl = coordinate_locations
-                    .entry(cds_vec[0].0)
-                    .or_insert_with(Vec::new);
-                cl.push(Location { x, y });
-            }
-            if total_distance < 10000 {
-                safe_region_count += 1;
-            }
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Owner {
+    Unvisited,
+    Coordinate(usize),
+    Tied,
+}
+
+#[derive(Debug, PartialEq)]
+struct Bounds {
+    min_x: u32,
+    min_y: u32,
+    max_x: u32,
+    max_y: u32,
+}
+
+impl Bounds {
+    fn new(coordinates: &[Location]) -> Bounds {
+        Bounds {
+            min_x: coordinates.iter().map(|c| c.x).min().unwrap(),
+            min_y: coordinates.iter().map(|c| c.y).min().unwrap(),
+            max_x: coordinates.iter().map(|c| c.x).max().unwrap(),
+            max_y: coordinates.iter().map(|c| c.y).max().unwrap(),
+        }
+    }
+
+    fn width(&self) -> usize {
+        (self.max_x - self.min_x) as usize + 1
+    }
+
+    fn height(&self) -> usize {
+        (self.max_y - self.min_y) as usize + 1
+    }
+}
+
+// Label every location in the bounding box with its closest coordinate,
+// breadth first from all coordinates at once. A shortest path between two
+// locations in the box stays in the box, so the breadth first distance is
+// the Manhattan distance, and a location is tied exactly when the locations
+// that lead to it are tied or have different owners.
+fn voronoi(coordinates: &[Location], bounds: &Bounds) -> Vec<Owner> {
+    let width = bounds.width();
+    let height = bounds.height();
+    let mut owners = vec![Owner::Unvisited; width * height];
+    let mut distances = vec![0; width * height];
+
+    let mut queue = VecDeque::new();
+    for (number, coordinate) in coordinates.iter().enumerate() {
+        let index =
+            (coordinate.y - bounds.min_y) as usize * width + (coordinate.x - bounds.min_x) as usize;
+        // A repeated coordinate is the same coordinate
+        if owners[index] == Owner::Unvisited {
+            owners[index] = Owner::Coordinate(number);
+            queue.push_back(index);
         }
     }
 
-    let mut locations_max = (0, None);
-    for (coordinate, locations) in coordinate_locations.iter() {
-        // Remove from consideration any coordinates with a location on an edge
-        if locations
-            .iter()
-            .any(|l| l.x == min_x || l.x == max_x || l.y == min_y || l.y == max_y)
-        {
-            continue;
+    while let Some(index) = queue.pop_front() {
+        let (x, y) = (index % width, index / width);
+        let mut neighbors = Vec::with_capacity(4);
+        if x > 0 {
+            neighbors.push(index - 1);
+        }
+        if x + 1 < width {
+            neighbors.push(index + 1);
         }
-        if locations.len() > locations_max.0 {
-            locations_max = (locations.len(), Some(coordinate));
+        if y > 0 {
+            neighbors.push(index - width);
+        }
+        if y + 1 < height {
+            neighbors.push(index + width);
+        }
+
+        for neighbor in neighbors {
+            if owners[neighbor] == Owner::Unvisited {
+                owners[neighbor] = owners[index];
+                distances[neighbor] = distances[index] + 1;
+                queue.push_back(neighbor);
+            } else if distances[neighbor] == distances[index] + 1
+                && owners[neighbor] != owners[index]
+            {
+                owners[neighbor] = Owner::Tied;
+            }
         }
     }
 
-    (locations_max.0 as u32, safe_region_count)
+    owners
 }
 
-fn main() {
-    let mut input = String::new();
-    stdin().read_to_string(&mut input).unwrap();
+// An area touching the edge of the bounding box goes on forever outside it
+fn largest_finite_area(coordinates: &[Location]) -> u32 {
+    let bounds = Bounds::new(coordinates);
+    let width = bounds.width();
+    let height = bounds.height();
+    let owners = voronoi(coordinates, &bounds);
+
+    let mut areas = vec![0; coordinates.len()];
+    let mut infinite = vec![false; coordinates.len()];
+    for (index, owner) in owners.iter().enumerate() {
+        if let Owner::Coordinate(number) = *owner {
+            areas[number] += 1;
+            let (x, y) = (index % width, index / width);
+            if x == 0 || y == 0 || x + 1 == width || y + 1 == height {
+                infinite[number] = true;
+            }
+        }
+    }
+
+    (0..coordinates.len())
+        .filter(|&number| !infinite[number])
+        .map(|number| areas[number])
+        .max()
+        .unwrap_or(0)
+}
+
+// The total distance to all coordinates along each axis, for every value
+// on that axis where it can still be under the threshold
+fn axis_distances(values: &[i64], threshold: i64) -> Vec<i64> {
+    let min = *values.iter().min().unwrap();
+    let max = *values.iter().max().unwrap();
+    // Past the extremes each step away adds a step to every distance
+    let margin = threshold / values.len() as i64 + 1;
+
+    (min - margin..=max + margin)
+        .map(|axis| values.iter().map(|value| (axis - value).abs()).sum())
+        .collect()
+}
 
-    let coordinates = input.lines().fold(Vec::new(), |mut acc, line| {
+// The region may reach beyond the bounding box. Distances along the two
+// axes add up independently, so count the pairs of column and row totals
+// under the threshold rather than visiting every location.
+fn safe_region_size(coordinates: &[Location], threshold: u32) -> u64 {
+    let threshold = threshold as i64;
+    let xs: Vec<i64> = coordinates.iter().map(|c| c.x as i64).collect();
+    let ys: Vec<i64> = coordinates.iter().map(|c| c.y as i64).collect();
+
+    let column_totals = axis_distances(&xs, threshold);
+    let mut row_totals = axis_distances(&ys, threshold);
+    row_totals.sort();
+
+    column_totals
+        .iter()
+        .map(|column_total| {
+            row_totals.partition_point(|row_total| column_total + row_total < threshold) as u64
+        })
+        .sum()
+}
+
+fn solve(coordinates: &[Location], threshold: u32) -> (u32, u64) {
+    if coordinates.is_empty() {
+        return (0, 0);
+    }
+
+    (
+        largest_finite_area(coordinates),
+        safe_region_size(coordinates, threshold),
+    )
+}
+
+fn parse(input: &str) -> Vec<Location> {
+    input.lines().fold(Vec::new(), |mut acc, line| {
         let mut fields = line.trim().split(", ");
         let x = fields.next().unwrap().parse().unwrap();
         let y = fields.next().unwrap().parse().unwrap();
         acc.push(Location { x, y });
         acc
-    });
+    })
+}
+
+fn main() {
+    let mut threshold = 10000;
 
-    let (part1, part2) = solve(&coordinates);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threshold" => {
+                threshold = args
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .expect("--threshold requires a number")
+            }
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+
+    let mut input = String::new();
+    stdin().read_to_string(&mut input).unwrap();
+
+    let coordinates = parse(&input);
+
+    let (part1, part2) = solve(&coordinates, threshold);
     println!(
         "Part 1: the size of the largest area that isn't infinite is {}",
         part1
     );
     println!("Part 2: the size of the region is {}", part2);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    const EXAMPLE: &str = "1, 1
+1, 6
+8, 3
+3, 4
+5, 5
+8, 9";
+
+    struct Generator {
+        seed: u64,
+    }
+
+    impl Generator {
+        fn next(&mut self, limit: u64) -> u64 {
+            self.seed = self
+                .seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.seed >> 33) % limit
+        }
+
+        fn coordinates(&mut self, quantity: usize, size: u64) -> Vec<Location> {
+            let mut coordinates = Vec::new();
+            while coordinates.len() < quantity {
+                let location = Location {
+                    x: self.next(size) as u32,
+                    y: self.next(size) as u32,
+                };
+                if !coordinates.contains(&location) {
+                    coordinates.push(location);
+                }
+            }
+            coordinates
+        }
+    }
+
+    // The previous solution, distances to every coordinate sorted for every
+    // location, over a box grown by margin so it holds the whole region
+    fn reference(coordinates: &[Location], threshold: i64, margin: i64) -> (u32, u64) {
+        let min_x = coordinates.iter().map(|c| c.x as i64).min().unwrap() - margin;
+        let min_y = coordinates.iter().map(|c| c.y as i64).min().unwrap() - margin;
+        let max_x = coordinates.iter().map(|c| c.x as i64).max().unwrap() + margin;
+        let max_y = coordinates.iter().map(|c| c.y as i64).max().unwrap() + margin;
+
+        let mut coordinate_locations: HashMap<Location, Vec<(i64, i64)>> = HashMap::new();
+        let mut safe_region_count = 0;
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let mut cds_vec = Vec::new();
+                let mut total_distance = 0;
+                for &coordinate in coordinates.iter() {
+                    let distance =
+                        (coordinate.x as i64 - x).abs() + (coordinate.y as i64 - y).abs();
+                    cds_vec.push((coordinate, distance));
+                    total_distance += distance;
+                }
+                cds_vec.sort_by_key(|a| a.1);
+                if cds_vec[0].1 < cds_vec[1].1 {
+                    let cl = coordinate_locations.entry(cds_vec[0].0).or_default();
+                    cl.push((x, y));
+                }
+                if total_distance < threshold {
+                    safe_region_count += 1;
+                }
+            }
+        }
+
+        let mut locations_max = 0;
+        for locations in coordinate_locations.values() {
+            if locations
+                .iter()
+                .any(|&(x, y)| x == min_x || x == max_x || y == min_y || y == max_y)
+            {
+                continue;
+            }
+            locations_max = locations_max.max(locations.len());
+        }
+
+        (locations_max as u32, safe_region_count)
+    }
+
+    #[test]
+    fn test_example() {
+        let coordinates = parse(EXAMPLE);
+        assert_eq!(solve(&coordinates, 32), (17, 16));
+    }
+
+    #[test]
+    fn test_bounds() {
+        let coordinates = parse("5, 7\n9, 3\n6, 12");
+        assert_eq!(
+            Bounds::new(&coordinates),
+            Bounds {
+                min_x: 5,
+                min_y: 3,
+                max_x: 9,
+                max_y: 12
+            }
+        );
+    }
+
+    #[test]
+    fn test_region_beyond_bounds() {
+        // One coordinate, so every location within 99 steps is in the region
+        let coordinates = parse("50, 50");
+        assert_eq!(solve(&coordinates, 100), (0, 100 * 100 + 99 * 99));
+    }
+
+    #[test]
+    fn test_repeated_coordinate() {
+        let coordinates = parse("0, 0\n4, 4\n2, 2\n2, 2\n0, 4\n4, 0");
+        let mut distinct = coordinates.clone();
+        distinct.dedup();
+        assert_eq!(largest_finite_area(&coordinates), 5);
+        assert_eq!(largest_finite_area(&distinct), 5);
+    }
+
+    #[test]
+    fn test_against_reference() {
+        let mut generator = Generator { seed: 6 };
+        for _ in 0..100 {
+            let quantity = 2 + generator.next(10) as usize;
+            // At least 16 locations to place up to 11 coordinates
+            let size = 4 + generator.next(30);
+            let coordinates = generator.coordinates(quantity, size);
+            let threshold = 1 + generator.next(300) as u32;
+
+            let margin = threshold as i64 / coordinates.len() as i64 + 1;
+            assert_eq!(
+                solve(&coordinates, threshold),
+                reference(&coordinates, threshold as i64, margin),
+                "{:?} {}",
+                coordinates,
+                threshold
+            );
+        }
+    }
+
+    // Times both parts for 50 coordinates over 300x300, like puzzle inputs
+    #[test]
+    #[ignore]
+    fn bench_solve() {
+        let mut generator = Generator { seed: 60 };
+        let coordinates = generator.coordinates(50, 300);
+
+        let start = Instant::now();
+        let (part1, part2) = solve(&coordinates, 10000);
+        println!("{} and {} in {:?}", part1, part2, start.elapsed());
+    }
+}
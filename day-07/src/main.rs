@@ -1,157 +1,730 @@
 use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
 use std::io::{stdin, Read};
 
 #[derive(Debug)]
-struct Delay {
-    seconds: u32,
+struct Requirement {
+    before: String,
+    after: String,
 }
 
-impl Into<Delay> for char {
-    fn into(self) -> Delay {
-        let seconds = match self {
-            'A' => 61,
-            'B' => 62,
-            'C' => 63,
-            'D' => 64,
-            'E' => 65,
-            'F' => 66,
-            'G' => 67,
-            'H' => 68,
-            'I' => 69,
-            'J' => 70,
-            'K' => 71,
-            'L' => 72,
-            'M' => 73,
-            'N' => 74,
-            'O' => 75,
-            'P' => 76,
-            'Q' => 77,
-            'R' => 78,
-            'S' => 79,
-            'T' => 80,
-            'U' => 81,
-            'V' => 82,
-            'W' => 83,
-            'X' => 84,
-            'Y' => 85,
-            'Z' => 86,
-            _ => 0,
-        };
-        Delay { seconds }
-    }
+// One task carried out by one worker from start until end
+#[derive(Debug, Clone, PartialEq)]
+struct Slot {
+    worker: usize,
+    task: String,
+    start: u32,
+    end: u32,
 }
 
 #[derive(Debug)]
-struct Requirement {
-    before: char,
-    after: char,
+struct Schedule {
+    order: Vec<String>,
+    timeline: Vec<Slot>,
+    seconds: u32,
 }
 
-fn solve(workers: usize, requirements: &[Requirement]) -> (String, u32) {
-    let mut letter_hs = HashSet::new();
-    for requirement in requirements.iter() {
-        letter_hs.insert(requirement.before);
-        letter_hs.insert(requirement.after);
-    }
-    let mut letters: Vec<char> = letter_hs.into_iter().collect();
-    letters.sort();
+// Task names can hold any characters, which must not break the markup
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
 
-    let mut obstructions: HashMap<char, Option<HashSet<char>>> = HashMap::new();
-    for &letter in letters.iter() {
-        obstructions.insert(letter, Some(HashSet::new()));
+impl Schedule {
+    fn workers(&self) -> usize {
+        self.timeline
+            .iter()
+            .map(|slot| slot.worker + 1)
+            .max()
+            .unwrap_or(0)
     }
-    for requirement in requirements.iter() {
-        if let Some(hs_opt) = obstructions.get_mut(&requirement.after) {
-            if let Some(hs) = hs_opt {
-                hs.insert(requirement.before);
+
+    // The table from the puzzle, one row for each second
+    fn table(&self, workers: usize) -> String {
+        let mut table = String::from("Second  ");
+        for worker in 0..workers {
+            table.push_str(&format!(" {:<10}", format!("Worker {}", worker + 1)));
+        }
+        table.push_str(" Done\n");
+
+        for second in 0..=self.seconds {
+            let mut row = format!("{:>4}    ", second);
+            for worker in 0..workers {
+                let task = self
+                    .timeline
+                    .iter()
+                    .find(|slot| slot.worker == worker && slot.start <= second && second < slot.end)
+                    .map_or(".", |slot| slot.task.as_str());
+                row.push_str(&format!("    {:<7}", task));
+            }
+            row.push(' ');
+            // The order is by completion, so the done steps are a prefix
+            for task in self.order.iter() {
+                let slot = self
+                    .timeline
+                    .iter()
+                    .find(|slot| &slot.task == task)
+                    .unwrap();
+                if slot.end > second {
+                    break;
+                }
+                row.push_str(task);
             }
+            table.push_str(row.trim_end());
+            table.push('\n');
         }
+
+        table
     }
 
-    let mut seconds = 0;
-    let mut delays: Vec<Option<(u32, char)>> = vec![None; 5];
-    let mut order: Vec<char> = Vec::new();
+    // A Gantt chart with a row for each worker, scale pixels to the second
+    fn to_svg(&self, scale: u32) -> String {
+        const LABEL_WIDTH: u32 = 80;
+        const ROW_HEIGHT: u32 = 30;
+        const MARGIN: u32 = 10;
 
-    loop {
+        let workers = self.workers() as u32;
+        let width = LABEL_WIDTH + self.seconds * scale + 2 * MARGIN;
+        let axis = MARGIN + workers * ROW_HEIGHT;
+        let height = axis + 2 * MARGIN + 10;
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"monospace\" font-size=\"12\">\n",
+            width, height
+        );
         for worker in 0..workers {
-            if None != delays[worker] {
-                continue;
+            svg.push_str(&format!(
+                "  <text x=\"{}\" y=\"{}\">Worker {}</text>\n",
+                MARGIN,
+                MARGIN + worker * ROW_HEIGHT + ROW_HEIGHT / 2 + 4,
+                worker + 1
+            ));
+        }
+        for slot in self.timeline.iter() {
+            let x = LABEL_WIDTH + MARGIN + slot.start * scale;
+            let y = MARGIN + slot.worker as u32 * ROW_HEIGHT;
+            let bar_width = (slot.end - slot.start) * scale;
+            svg.push_str(&format!(
+                "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#8ab\" stroke=\"#234\"><title>{} from {} to {}</title></rect>\n",
+                x,
+                y + 2,
+                bar_width,
+                ROW_HEIGHT - 4,
+                escape_xml(&slot.task),
+                slot.start,
+                slot.end
+            ));
+            svg.push_str(&format!(
+                "  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                x + bar_width / 2,
+                y + ROW_HEIGHT / 2 + 4,
+                escape_xml(&slot.task)
+            ));
+        }
+
+        // A time axis with about ten ticks
+        svg.push_str(&format!(
+            "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#000\"/>\n",
+            LABEL_WIDTH + MARGIN,
+            axis,
+            LABEL_WIDTH + MARGIN + self.seconds * scale,
+            axis
+        ));
+        let tick = (self.seconds / 10).max(1);
+        for second in (0..=self.seconds).step_by(tick as usize) {
+            svg.push_str(&format!(
+                "  <text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                LABEL_WIDTH + MARGIN + second * scale,
+                axis + MARGIN + 6,
+                second
+            ));
+        }
+        svg.push_str("</svg>\n");
+
+        svg
+    }
+}
+
+// Steps that require each other in a loop, each before the next and the
+// last before the first
+#[derive(Debug, PartialEq)]
+struct Cycle {
+    steps: Vec<String>,
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "the steps form a cycle: {} -> {}",
+            self.steps.join(" -> "),
+            self.steps[0]
+        )
+    }
+}
+
+// Slack is how long a step can be delayed without delaying the end,
+// given as many workers as there are steps
+#[derive(Debug)]
+struct CriticalPath {
+    path: Vec<String>,
+    seconds: u32,
+    slack: Vec<(String, u32)>,
+}
+
+// Tasks are numbered in alphabetical order, so the lowest available
+// number is always the task to start next
+struct Graph {
+    tasks: Vec<String>,
+    prerequisites: Vec<Vec<usize>>,
+    dependents: Vec<Vec<usize>>,
+}
+
+impl Graph {
+    fn new(requirements: &[Requirement]) -> Graph {
+        let mut tasks: Vec<String> = requirements
+            .iter()
+            .flat_map(|requirement| vec![requirement.before.clone(), requirement.after.clone()])
+            .collect();
+        tasks.sort();
+        tasks.dedup();
+
+        let numbers: HashMap<&str, usize> = tasks
+            .iter()
+            .enumerate()
+            .map(|(number, task)| (task.as_str(), number))
+            .collect();
+
+        let mut prerequisites = vec![Vec::new(); tasks.len()];
+        let mut dependents = vec![Vec::new(); tasks.len()];
+        for requirement in requirements.iter() {
+            let before = numbers[requirement.before.as_str()];
+            let after = numbers[requirement.after.as_str()];
+            if !prerequisites[after].contains(&before) {
+                prerequisites[after].push(before);
+                dependents[before].push(after);
             }
-            let mut next_letter_opt = None;
-            for letter in letters.iter() {
-                if order.contains(&letter) {
-                    continue;
-                }
-                if let Some(obstruction_opt) = obstructions.get_mut(&letter) {
-                    if let Some(obstruction) = obstruction_opt {
-                        if obstruction.is_empty() {
-                            next_letter_opt = Some(*letter);
-                            *obstruction_opt = None;
-                            break;
-                        }
-                    }
+        }
+
+        Graph {
+            tasks,
+            prerequisites,
+            dependents,
+        }
+    }
+
+    // Kahn's algorithm. When it gets stuck, every remaining task still has a
+    // remaining prerequisite, so following those must lead around a cycle.
+    fn topological_order(&self) -> Result<Vec<usize>, Cycle> {
+        let mut waiting: Vec<usize> = self.prerequisites.iter().map(|p| p.len()).collect();
+        let mut ready: Vec<usize> = (0..self.tasks.len())
+            .filter(|&number| waiting[number] == 0)
+            .collect();
+        let mut order = Vec::new();
+        while let Some(number) = ready.pop() {
+            order.push(number);
+            for &dependent in self.dependents[number].iter() {
+                waiting[dependent] -= 1;
+                if waiting[dependent] == 0 {
+                    ready.push(dependent);
                 }
             }
-            if let Some(next_letter) = next_letter_opt {
-                let delay: Delay = next_letter.into();
-                delays[worker] = Some((delay.seconds, next_letter));
+        }
+
+        if order.len() == self.tasks.len() {
+            return Ok(order);
+        }
+
+        let mut seen = vec![None; self.tasks.len()];
+        let mut walk = Vec::new();
+        let mut number = (0..self.tasks.len())
+            .find(|&number| waiting[number] > 0)
+            .unwrap();
+        while seen[number].is_none() {
+            seen[number] = Some(walk.len());
+            walk.push(number);
+            number = *self.prerequisites[number]
+                .iter()
+                .find(|&&prerequisite| waiting[prerequisite] > 0)
+                .unwrap();
+        }
+        // The walk went backwards through the prerequisites
+        let mut steps: Vec<String> = walk[seen[number].unwrap()..]
+            .iter()
+            .rev()
+            .map(|&number| self.tasks[number].clone())
+            .collect();
+        // Start the cycle at its alphabetically first step
+        let first = (0..steps.len()).min_by_key(|&i| &steps[i]).unwrap();
+        steps.rotate_left(first);
+
+        Err(Cycle { steps })
+    }
+
+    fn critical_path<F>(&self, duration: F) -> Result<CriticalPath, Cycle>
+    where
+        F: Fn(&str) -> u32,
+    {
+        let order = self.topological_order()?;
+        let durations: Vec<u32> = self.tasks.iter().map(|task| duration(task)).collect();
+
+        let mut earliest_start = vec![0; self.tasks.len()];
+        for &number in order.iter() {
+            for &dependent in self.dependents[number].iter() {
+                let finish = earliest_start[number] + durations[number];
+                earliest_start[dependent] = earliest_start[dependent].max(finish);
+            }
+        }
+        let seconds = (0..self.tasks.len())
+            .map(|number| earliest_start[number] + durations[number])
+            .max()
+            .unwrap_or(0);
+
+        let mut latest_finish = vec![seconds; self.tasks.len()];
+        for &number in order.iter().rev() {
+            for &dependent in self.dependents[number].iter() {
+                let start = latest_finish[dependent] - durations[dependent];
+                latest_finish[number] = latest_finish[number].min(start);
             }
         }
 
-        if order.len() == letters.len() {
-            break;
+        let slack = (0..self.tasks.len())
+            .map(|number| {
+                let latest_start = latest_finish[number] - durations[number];
+                (
+                    self.tasks[number].clone(),
+                    latest_start - earliest_start[number],
+                )
+            })
+            .collect();
+
+        // Walk back from the last step to finish through prerequisites that
+        // finish exactly when the step can start, alphabetically first
+        let mut path = Vec::new();
+        let mut current = (0..self.tasks.len())
+            .find(|&number| earliest_start[number] + durations[number] == seconds);
+        while let Some(number) = current {
+            path.push(self.tasks[number].clone());
+            let mut prerequisites = self.prerequisites[number].clone();
+            prerequisites.sort();
+            current = prerequisites.into_iter().find(|&prerequisite| {
+                earliest_start[prerequisite] + durations[prerequisite] == earliest_start[number]
+            });
         }
+        path.reverse();
 
-        seconds += 1;
+        Ok(CriticalPath {
+            path,
+            seconds,
+            slack,
+        })
+    }
 
-        for worker in 0..workers {
-            if let Some((mut delay, next_letter)) = delays[worker] {
-                delay -= 1;
-
-                if delay == 0 {
-                    order.push(next_letter);
-
-                    for &letter in letters.iter() {
-                        if let Some(hs_opt) = obstructions.get_mut(&letter) {
-                            if let Some(hs) = hs_opt {
-                                hs.remove(&next_letter);
-                            }
-                        }
+    // Idle workers take the alphabetically first available task, lowest
+    // numbered worker first. Tasks finishing at the same moment are done
+    // in alphabetical order.
+    fn schedule<F>(&self, workers: usize, duration: F) -> Result<Schedule, Cycle>
+    where
+        F: Fn(&str) -> u32,
+    {
+        assert!(workers > 0, "At least one worker is required");
+        self.topological_order()?;
+
+        let mut waiting: Vec<usize> = self.prerequisites.iter().map(|p| p.len()).collect();
+        let mut available: BTreeSet<usize> = (0..self.tasks.len())
+            .filter(|&number| waiting[number] == 0)
+            .collect();
+        // The task each worker is busy with, and when it ends
+        let mut busy: Vec<Option<(usize, u32)>> = vec![None; workers];
+
+        let mut seconds = 0;
+        let mut order = Vec::new();
+        let mut timeline = Vec::new();
+
+        loop {
+            let mut finished: Vec<usize> = Vec::new();
+            for slot in busy.iter_mut() {
+                if let Some((number, end)) = *slot {
+                    if end == seconds {
+                        finished.push(number);
+                        *slot = None;
+                    }
+                }
+            }
+            finished.sort();
+            for &number in finished.iter() {
+                order.push(self.tasks[number].clone());
+                for &dependent in self.dependents[number].iter() {
+                    waiting[dependent] -= 1;
+                    if waiting[dependent] == 0 {
+                        available.insert(dependent);
                     }
+                }
+            }
 
-                    delays[worker] = None;
-                } else {
-                    delays[worker] = Some((delay, next_letter));
+            for (worker, slot) in busy.iter_mut().enumerate() {
+                if slot.is_some() {
+                    continue;
                 }
+                let number = match available.iter().next() {
+                    Some(&number) => number,
+                    None => break,
+                };
+                available.remove(&number);
+
+                let task = self.tasks[number].clone();
+                let end = seconds + duration(&task);
+                *slot = Some((number, end));
+                timeline.push(Slot {
+                    worker,
+                    task,
+                    start: seconds,
+                    end,
+                });
+            }
+
+            match busy.iter().flatten().map(|&(_, end)| end).min() {
+                Some(end) => seconds = end,
+                None => break,
             }
         }
+
+        Ok(Schedule {
+            order,
+            timeline,
+            seconds,
+        })
     }
+}
 
-    (order.iter().collect(), seconds)
+// The puzzle's durations: a base plus A=1, B=2, C=3 and so on
+fn step_duration(base: u32, task: &str) -> u32 {
+    task.chars()
+        .filter(|c| c.is_ascii_uppercase())
+        .map(|c| c as u32 - 'A' as u32 + 1)
+        .sum::<u32>()
+        + base
 }
 
-fn main() {
-    let mut input = String::new();
-    stdin().read_to_string(&mut input).unwrap();
+fn solve(workers: usize, base: u32, requirements: &[Requirement]) -> Result<(String, u32), Cycle> {
+    let schedule = Graph::new(requirements).schedule(workers, |task| step_duration(base, task))?;
 
-    let mut requirements = Vec::new();
+    Ok((schedule.order.concat(), schedule.seconds))
+}
 
-    let rule = r"Step (\D) must be finished before step (\D) can begin.";
+fn parse(input: &str) -> Vec<Requirement> {
+    let rule = r"Step (\S+) must be finished before step (\S+) can begin.";
     let re = Regex::new(rule).unwrap();
 
-    for capture in re.captures_iter(input.trim()) {
-        let before = capture[1].chars().next().unwrap();
-        let after = capture[2].chars().next().unwrap();
-        requirements.push(Requirement { before, after });
+    re.captures_iter(input.trim())
+        .map(|capture| Requirement {
+            before: capture[1].to_string(),
+            after: capture[2].to_string(),
+        })
+        .collect()
+}
+
+fn main() -> Result<(), String> {
+    let mut workers = 5;
+    let mut base = 60;
+    let mut show_timeline = false;
+    let mut show_critical_path = false;
+    let mut show_table = false;
+    let mut svg_path = None;
+    let mut scale = 10;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--workers" => {
+                workers = args
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .expect("--workers requires a number")
+            }
+            "--base" => {
+                base = args
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .expect("--base requires a number")
+            }
+            "--timeline" => show_timeline = true,
+            "--critical-path" => show_critical_path = true,
+            "--table" => show_table = true,
+            "--svg" => svg_path = Some(args.next().expect("--svg requires a path")),
+            "--scale" => {
+                scale = args
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .expect("--scale requires a number")
+            }
+            _ => panic!("Unknown argument {}", arg),
+        }
     }
 
-    let (order, _seconds) = solve(1, &requirements);
+    let mut input = String::new();
+    stdin().read_to_string(&mut input).unwrap();
+
+    let requirements = parse(&input);
+
+    let (order, _seconds) = solve(1, base, &requirements).map_err(|cycle| cycle.to_string())?;
     println!(
         "Part 1: the steps should be completed in this order: {}",
         order
     );
 
-    let (_order, seconds) = solve(5, &requirements);
-    println!("Part 2: It will take {} seconds to complete", seconds);
+    let graph = Graph::new(&requirements);
+    let schedule = graph
+        .schedule(workers, |task| step_duration(base, task))
+        .map_err(|cycle| cycle.to_string())?;
+    println!(
+        "Part 2: It will take {} seconds to complete",
+        schedule.seconds
+    );
+
+    if show_timeline {
+        for slot in schedule.timeline.iter() {
+            println!(
+                "Worker {} does {} from {} to {}",
+                slot.worker + 1,
+                slot.task,
+                slot.start,
+                slot.end
+            );
+        }
+    }
+
+    if show_table {
+        print!("{}", schedule.table(workers));
+    }
+
+    if let Some(path) = svg_path {
+        fs::write(path, schedule.to_svg(scale)).expect("Unable to write SVG file");
+    }
+
+    if show_critical_path {
+        let critical_path = graph
+            .critical_path(|task| step_duration(base, task))
+            .map_err(|cycle| cycle.to_string())?;
+        println!(
+            "No number of workers can finish in under {} seconds: {}",
+            critical_path.seconds,
+            critical_path.path.join(" -> ")
+        );
+        for (task, slack) in critical_path.slack.iter() {
+            println!("Step {} has {} seconds of slack", task, slack);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Step C must be finished before step A can begin.
+Step C must be finished before step F can begin.
+Step A must be finished before step B can begin.
+Step A must be finished before step D can begin.
+Step B must be finished before step E can begin.
+Step D must be finished before step E can begin.
+Step F must be finished before step E can begin.";
+
+    fn slot(worker: usize, task: &str, start: u32, end: u32) -> Slot {
+        Slot {
+            worker,
+            task: task.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_part1() {
+        let requirements = parse(EXAMPLE);
+        assert_eq!(solve(1, 0, &requirements).unwrap().0, "CABDFE");
+    }
+
+    #[test]
+    fn test_part2() {
+        let requirements = parse(EXAMPLE);
+        assert_eq!(solve(2, 0, &requirements), Ok((String::from("CABFDE"), 15)));
+    }
+
+    #[test]
+    fn test_timeline() {
+        let graph = Graph::new(&parse(EXAMPLE));
+        let schedule = graph.schedule(2, |task| step_duration(0, task)).unwrap();
+        assert_eq!(
+            schedule.timeline,
+            vec![
+                slot(0, "C", 0, 3),
+                slot(0, "A", 3, 4),
+                slot(1, "F", 3, 9),
+                slot(0, "B", 4, 6),
+                slot(0, "D", 6, 10),
+                slot(0, "E", 10, 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let graph = Graph::new(&parse(EXAMPLE));
+        let schedule = graph.schedule(2, |task| step_duration(0, task)).unwrap();
+        let expected = "Second   Worker 1   Worker 2   Done
+   0        C          .
+   1        C          .
+   2        C          .
+   3        A          F       C
+   4        B          F       CA
+   5        B          F       CA
+   6        D          F       CAB
+   7        D          F       CAB
+   8        D          F       CAB
+   9        D          .       CABF
+  10        E          .       CABFD
+  11        E          .       CABFD
+  12        E          .       CABFD
+  13        E          .       CABFD
+  14        E          .       CABFD
+  15        .          .       CABFDE
+";
+        assert_eq!(schedule.table(2), expected);
+    }
+
+    #[test]
+    fn test_svg() {
+        let graph = Graph::new(&parse(EXAMPLE));
+        let schedule = graph.schedule(2, |task| step_duration(0, task)).unwrap();
+        let svg = schedule.to_svg(10);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), 6);
+        assert!(svg.contains(
+            "<rect x=\"120\" y=\"42\" width=\"60\" height=\"26\" fill=\"#8ab\" stroke=\"#234\"><title>F from 3 to 9</title></rect>"
+        ));
+        assert!(svg.contains(">Worker 2</text>"));
+
+        let input = "Step a&b must be finished before step <x> can begin.
+Step <x> must be finished before step \"q\" can begin.";
+        let graph = Graph::new(&parse(input));
+        let svg = graph.schedule(1, |_| 1).unwrap().to_svg(10);
+        assert!(svg.contains("<title>a&amp;b from 0 to 1</title>"));
+        assert!(svg.contains(">&lt;x&gt;</text>"));
+        assert!(svg.contains(">&quot;q&quot;</text>"));
+        assert!(!svg.contains("a&b"));
+    }
+
+    #[test]
+    fn test_many_workers() {
+        let graph = Graph::new(&parse(EXAMPLE));
+        let schedule = graph.schedule(8, |task| step_duration(60, task)).unwrap();
+        assert_eq!(schedule.seconds, 63 + 61 + 64 + 65);
+        assert!(schedule.timeline.iter().all(|slot| slot.worker < 3));
+    }
+
+    #[test]
+    fn test_named_tasks() {
+        let input = "Step fetch must be finished before step build can begin.
+Step build must be finished before step test can begin.
+Step build must be finished before step lint can begin.
+Step lint must be finished before step release can begin.
+Step test must be finished before step release can begin.";
+        let graph = Graph::new(&parse(input));
+        let schedule = graph.schedule(3, |task| task.len() as u32).unwrap();
+        assert_eq!(
+            schedule.order,
+            vec!["fetch", "build", "lint", "test", "release"]
+        );
+        assert_eq!(schedule.seconds, 5 + 5 + 4 + 7);
+    }
+
+    #[test]
+    fn test_instant_tasks() {
+        let graph = Graph::new(&parse(EXAMPLE));
+        let schedule = graph.schedule(1, |_| 0).unwrap();
+        assert_eq!(schedule.order.concat(), "CABDFE");
+        assert_eq!(schedule.seconds, 0);
+    }
+
+    #[test]
+    fn test_cycle() {
+        let input = "Step A must be finished before step B can begin.
+Step D must be finished before step C can begin.
+Step B must be finished before step D can begin.
+Step C must be finished before step B can begin.";
+        let cycle = Cycle {
+            steps: vec![String::from("B"), String::from("D"), String::from("C")],
+        };
+        assert_eq!(
+            cycle.to_string(),
+            "the steps form a cycle: B -> D -> C -> B"
+        );
+        assert_eq!(solve(2, 0, &parse(input)), Err(cycle));
+
+        let input = "Step A must be finished before step A can begin.";
+        let graph = Graph::new(&parse(input));
+        assert_eq!(
+            graph.critical_path(|_| 1).err(),
+            Some(Cycle {
+                steps: vec![String::from("A")]
+            })
+        );
+    }
+
+    #[test]
+    fn test_critical_path() {
+        let graph = Graph::new(&parse(EXAMPLE));
+        let critical_path = graph.critical_path(|task| step_duration(0, task)).unwrap();
+        assert_eq!(critical_path.path, vec!["C", "F", "E"]);
+        assert_eq!(critical_path.seconds, 14);
+
+        let slack: Vec<u32> = critical_path
+            .slack
+            .iter()
+            .map(|&(_, slack)| slack)
+            .collect();
+        // A B C D E F
+        assert_eq!(slack, vec![1, 3, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_lower_bound() {
+        // With the puzzle's durations, D rather than F holds up E
+        let graph = Graph::new(&parse(EXAMPLE));
+        let duration = |task: &str| step_duration(60, task);
+        let critical_path = graph.critical_path(duration).unwrap();
+        assert_eq!(critical_path.path, vec!["C", "A", "D", "E"]);
+        assert_eq!(critical_path.seconds, 63 + 61 + 64 + 65);
+        for workers in 1..=6 {
+            let schedule = graph.schedule(workers, duration).unwrap();
+            assert!(schedule.seconds >= critical_path.seconds);
+        }
+        assert_eq!(graph.schedule(1, duration).unwrap().seconds, 381);
+
+        // Two steps tie for the longest chain, so neither has slack
+        let input = "Step fetch must be finished before step build can begin.
+Step build must be finished before step test can begin.
+Step build must be finished before step lint can begin.
+Step lint must be finished before step release can begin.
+Step test must be finished before step release can begin.";
+        let graph = Graph::new(&parse(input));
+        let duration = |task: &str| task.len() as u32;
+        let critical_path = graph.critical_path(duration).unwrap();
+        assert_eq!(
+            critical_path.path,
+            vec!["fetch", "build", "lint", "release"]
+        );
+        assert_eq!(critical_path.seconds, 21);
+        assert!(critical_path.slack.iter().all(|&(_, slack)| slack == 0));
+        assert_eq!(graph.schedule(1, duration).unwrap().seconds, 25);
+        assert_eq!(graph.schedule(2, duration).unwrap().seconds, 21);
+    }
 }
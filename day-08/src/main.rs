@@ -1,5 +1,48 @@
+use std::env;
+use std::fmt;
+use std::fs;
 use std::io::{stdin, Read};
 
+// Offsets count numbers from the start of the input, not characters
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    InvalidNumber {
+        offset: usize,
+        text: String,
+    },
+    Truncated {
+        offset: usize,
+        expected: &'static str,
+    },
+    TrailingNumbers {
+        offset: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidNumber { offset, text } => {
+                write!(f, "'{}' at offset {} is not a number", text, offset)
+            }
+            ParseError::Truncated { offset, expected } => {
+                write!(
+                    f,
+                    "the input ends at offset {} where {} was expected",
+                    offset, expected
+                )
+            }
+            ParseError::TrailingNumbers { offset } => {
+                write!(
+                    f,
+                    "the tree ends at offset {} but more numbers follow",
+                    offset
+                )
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Node {
     index: usize,
@@ -43,27 +86,115 @@ impl Engine {
         }
     }
 
-    fn read_node(&mut self) {
-        let child_quantity = self.numbers[self.numbers_index];
-        self.numbers_index += 1;
-        let metadata_quantity = self.numbers[self.numbers_index];
-        self.numbers_index += 1;
+    fn next_number(&mut self, expected: &'static str) -> Result<u32, ParseError> {
+        match self.numbers.get(self.numbers_index) {
+            Some(&number) => {
+                self.numbers_index += 1;
+                Ok(number)
+            }
+            None => Err(ParseError::Truncated {
+                offset: self.numbers_index,
+                expected,
+            }),
+        }
+    }
+
+    fn read_header(&mut self) -> Result<usize, ParseError> {
+        let child_quantity = self.next_number("a child quantity")?;
+        let metadata_quantity = self.next_number("a metadata quantity")?;
 
         let own_node_index = self.next_node_index;
         self.next_node_index += 1;
         let node = Node::new(own_node_index, child_quantity, metadata_quantity);
         self.nodes.push(node);
 
-        for _child in 0..child_quantity {
-            let child_node_index = self.next_node_index;
-            self.nodes[own_node_index].children.push(child_node_index);
-            self.read_node();
+        Ok(own_node_index)
+    }
+
+    // Read the tree without recursion, so deep trees cannot overflow the
+    // stack. Nodes are numbered in the order their headers appear.
+    fn read_node(&mut self) -> Result<(), ParseError> {
+        // Each node on the stack still has children or metadata to read
+        let mut stack = vec![self.read_header()?];
+        while let Some(&own_node_index) = stack.last() {
+            let node = &self.nodes[own_node_index];
+            if node.children.len() < node.child_quantity as usize {
+                let child_node_index = self.read_header()?;
+                self.nodes[own_node_index].children.push(child_node_index);
+                stack.push(child_node_index);
+            } else {
+                for _metadata in 0..node.metadata_quantity {
+                    let metadata = self.next_number("a metadata entry")?;
+                    self.nodes[own_node_index].metadata.push(metadata);
+                }
+                stack.pop();
+            }
         }
-        for _metadata in 0..metadata_quantity {
-            let metadata = self.numbers[self.numbers_index];
-            self.numbers_index += 1;
-            self.nodes[own_node_index].metadata.push(metadata);
+
+        if self.numbers_index < self.numbers.len() {
+            return Err(ParseError::TrailingNumbers {
+                offset: self.numbers_index,
+            });
         }
+
+        Ok(())
+    }
+
+    // Build a tree by hand: the root comes first, then every node is added
+    // after its parent and becomes that parent's last child
+    fn add_node(&mut self, parent: Option<usize>, metadata: Vec<u32>) -> usize {
+        assert_eq!(
+            parent.is_none(),
+            self.nodes.is_empty(),
+            "Only the first node is the root"
+        );
+
+        let own_node_index = self.next_node_index;
+        self.next_node_index += 1;
+        self.nodes.push(Node::new(own_node_index, 0, 0));
+        self.set_metadata(own_node_index, metadata);
+
+        if let Some(parent) = parent {
+            self.nodes[parent].child_quantity += 1;
+            self.nodes[parent].children.push(own_node_index);
+        }
+
+        own_node_index
+    }
+
+    fn set_metadata(&mut self, index: usize, metadata: Vec<u32>) {
+        self.nodes[index].metadata_quantity = metadata.len() as u32;
+        self.nodes[index].metadata = metadata;
+    }
+
+    // The inverse of read_node: the tree as a license number stream
+    fn encode(&self) -> Vec<u32> {
+        let mut numbers = Vec::new();
+        if self.nodes.is_empty() {
+            return numbers;
+        }
+
+        let header = |numbers: &mut Vec<u32>, node: &Node| {
+            numbers.push(node.children.len() as u32);
+            numbers.push(node.metadata.len() as u32);
+        };
+
+        header(&mut numbers, &self.nodes[0]);
+        // Each node on the stack with the position of its next child
+        let mut stack = vec![(0, 0)];
+        while let Some((index, position)) = stack.pop() {
+            let node = &self.nodes[index];
+            match node.children.get(position) {
+                Some(&child) => {
+                    header(&mut numbers, &self.nodes[child]);
+                    stack.push((index, position + 1));
+                    stack.push((child, 0));
+                }
+                None => numbers.extend(&node.metadata),
+            }
+        }
+
+        numbers
     }
 
     fn metadata_sum(&self) -> u32 {
@@ -100,24 +231,455 @@ impl Engine {
 
         values[0]
     }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph license {\n");
+
+        for node in self.nodes.iter() {
+            let metadata: Vec<String> = node.metadata.iter().map(|x| x.to_string()).collect();
+            dot.push_str(&format!(
+                "    n{} [label=\"{}: {}\"];\n",
+                node.index,
+                node.index,
+                metadata.join(" ")
+            ));
+        }
+        for node in self.nodes.iter() {
+            for child in node.children.iter() {
+                dot.push_str(&format!("    n{} -> n{};\n", node.index, child));
+            }
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+
+    // Nested objects, written without recursion like the parser
+    fn to_json(&self) -> String {
+        if self.nodes.is_empty() {
+            return String::from("null");
+        }
+
+        let open = |node: &Node| {
+            let metadata: Vec<String> = node.metadata.iter().map(|x| x.to_string()).collect();
+            format!("{{\"metadata\":[{}],\"children\":[", metadata.join(","))
+        };
+
+        let mut json = open(&self.nodes[0]);
+        // Each node on the stack with the position of its next child
+        let mut stack = vec![(0, 0)];
+        while let Some((index, position)) = stack.pop() {
+            let node = &self.nodes[index];
+            match node.children.get(position) {
+                Some(&child) => {
+                    if position > 0 {
+                        json.push(',');
+                    }
+                    json.push_str(&open(&self.nodes[child]));
+                    stack.push((index, position + 1));
+                    stack.push((child, 0));
+                }
+                None => json.push_str("]}"),
+            }
+        }
+
+        json
+    }
+}
+
+fn parse_numbers(input: &str) -> Result<Vec<u32>, ParseError> {
+    input
+        .split_whitespace()
+        .enumerate()
+        .map(|(offset, text)| {
+            text.parse().map_err(|_| ParseError::InvalidNumber {
+                offset,
+                text: text.to_string(),
+            })
+        })
+        .collect()
+}
+
+struct JsonReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    fn new(input: &'a str) -> JsonReader<'a> {
+        JsonReader {
+            bytes: input.as_bytes(),
+            position: 0,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn error(&self, expected: &str) -> String {
+        format!("expected {} at byte {}", expected, self.position)
+    }
+
+    fn consume(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.bytes.get(self.position) == Some(&byte) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.consume(byte) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{}'", byte as char)))
+        }
+    }
+
+    fn key(&mut self) -> Result<&'a str, String> {
+        self.expect(b'"')?;
+        let start = self.position;
+        while self.position < self.bytes.len() && self.bytes[self.position] != b'"' {
+            self.position += 1;
+        }
+        let key = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
+        self.expect(b'"')?;
+        self.expect(b':')?;
+
+        Ok(key)
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        self.skip_whitespace();
+        let start = self.position;
+        while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_digit() {
+            self.position += 1;
+        }
+        let digits = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
+        digits.parse().map_err(|_| {
+            self.position = start;
+            self.error("a number")
+        })
+    }
+
+    fn numbers(&mut self) -> Result<Vec<u32>, String> {
+        let mut numbers = Vec::new();
+        self.expect(b'[')?;
+        if self.consume(b']') {
+            return Ok(numbers);
+        }
+        loop {
+            numbers.push(self.number()?);
+            if self.consume(b']') {
+                return Ok(numbers);
+            }
+            self.expect(b',')?;
+        }
+    }
 }
 
-fn main() {
-    let mut input = String::new();
-    stdin().read_to_string(&mut input).unwrap();
+// Read the nested objects written by to_json, again without recursion
+fn from_json(input: &str) -> Result<Engine, String> {
+    let mut reader = JsonReader::new(input);
+    let mut engine = Engine::new(Vec::new());
 
-    let numbers: Vec<u32> = input
-        .trim()
-        .split(' ')
-        .map(|x| x.parse().unwrap())
-        .collect();
+    reader.expect(b'{')?;
+    // Nodes whose objects are still open, innermost last
+    let mut stack = vec![engine.add_node(None, Vec::new())];
+    let mut first_member = true;
+    while let Some(&index) = stack.last() {
+        if reader.consume(b'}') {
+            stack.pop();
+            if stack.is_empty() {
+                break;
+            }
+            // Back in the children array of the parent
+            if reader.consume(b',') {
+                reader.expect(b'{')?;
+                stack.push(engine.add_node(stack.last().copied(), Vec::new()));
+                first_member = true;
+            } else {
+                reader.expect(b']')?;
+                first_member = false;
+            }
+            continue;
+        }
 
-    let mut engine = Engine::new(numbers);
-    engine.read_node();
+        if !first_member && !reader.consume(b',') {
+            return Err(reader.error("',' or '}'"));
+        }
+        first_member = false;
+        match reader.key()? {
+            "metadata" => {
+                let metadata = reader.numbers()?;
+                engine.set_metadata(index, metadata);
+            }
+            "children" => {
+                reader.expect(b'[')?;
+                if !reader.consume(b']') {
+                    reader.expect(b'{')?;
+                    stack.push(engine.add_node(Some(index), Vec::new()));
+                    first_member = true;
+                }
+            }
+            _ => return Err(reader.error("\"metadata\" or \"children\"")),
+        }
+    }
+
+    reader.skip_whitespace();
+    if reader.position < reader.bytes.len() {
+        return Err(reader.error("the end of the input"));
+    }
+
+    Ok(engine)
+}
+
+fn parse(input: &str) -> Result<Engine, ParseError> {
+    let mut engine = Engine::new(parse_numbers(input)?);
+    engine.read_node()?;
+
+    Ok(engine)
+}
+
+fn main() -> Result<(), String> {
+    let mut dot_path = None;
+    let mut json_path = None;
+    let mut encode_path = None;
+    let mut from_json_path = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dot" => dot_path = Some(args.next().expect("--dot requires a path")),
+            "--json" => json_path = Some(args.next().expect("--json requires a path")),
+            "--encode" => encode_path = Some(args.next().expect("--encode requires a path")),
+            "--from-json" => {
+                from_json_path = Some(args.next().expect("--from-json requires a path"))
+            }
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+
+    // The tree comes from stdin unless --from-json names a JSON file
+    let mut engine = match from_json_path {
+        Some(path) => {
+            let input = fs::read_to_string(path).expect("Unable to read JSON file");
+            from_json(&input)?
+        }
+        None => {
+            let mut input = String::new();
+            stdin().read_to_string(&mut input).unwrap();
+            parse(&input).map_err(|error| error.to_string())?
+        }
+    };
 
     let part1 = engine.metadata_sum();
     println!("Part 1: the sum of all metadata entries is {}", part1);
 
     let part2 = engine.root_node_value();
     println!("Part 2: the value of the root node is {}", part2);
+
+    if let Some(path) = dot_path {
+        fs::write(path, engine.to_dot()).expect("Unable to write DOT file");
+    }
+    if let Some(path) = json_path {
+        fs::write(path, engine.to_json()).expect("Unable to write JSON file");
+    }
+    if let Some(path) = encode_path {
+        let numbers: Vec<String> = engine.encode().iter().map(|x| x.to_string()).collect();
+        fs::write(path, numbers.join(" ") + "\n").expect("Unable to write license file");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2";
+
+    struct Generator {
+        seed: u64,
+    }
+
+    impl Generator {
+        fn next(&mut self, limit: u64) -> u64 {
+            self.seed = self
+                .seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.seed >> 33) % limit
+        }
+    }
+
+    // A random tree whose metadata sometimes points past the children
+    fn random_tree(generator: &mut Generator, size: usize) -> Engine {
+        let mut engine = Engine::new(Vec::new());
+        engine.add_node(None, Vec::new());
+        for _ in 1..size {
+            let parent = generator.next(engine.nodes.len() as u64) as usize;
+            engine.add_node(Some(parent), Vec::new());
+        }
+        for index in 0..size {
+            let limit = engine.nodes[index].children.len() as u64 + 2;
+            let metadata = (0..generator.next(4))
+                .map(|_| generator.next(limit) as u32)
+                .collect();
+            engine.set_metadata(index, metadata);
+        }
+
+        engine
+    }
+
+    #[test]
+    fn test_part1() {
+        let engine = parse(EXAMPLE).unwrap();
+        assert_eq!(engine.metadata_sum(), 138);
+    }
+
+    #[test]
+    fn test_part2() {
+        let mut engine = parse(EXAMPLE).unwrap();
+        assert_eq!(engine.root_node_value(), 66);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            parse("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1").err(),
+            Some(ParseError::Truncated {
+                offset: 15,
+                expected: "a metadata entry"
+            })
+        );
+        assert_eq!(
+            parse("1 1 0").err(),
+            Some(ParseError::Truncated {
+                offset: 3,
+                expected: "a metadata quantity"
+            })
+        );
+        assert_eq!(
+            parse("").err(),
+            Some(ParseError::Truncated {
+                offset: 0,
+                expected: "a child quantity"
+            })
+        );
+        assert_eq!(
+            parse("0 1 5 7").err(),
+            Some(ParseError::TrailingNumbers { offset: 3 })
+        );
+        assert_eq!(
+            parse("0 2 5 x").err(),
+            Some(ParseError::InvalidNumber {
+                offset: 3,
+                text: String::from("x")
+            })
+        );
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        // A chain of nodes each with one child, far deeper than recursion allows
+        let depth = 1_000_000;
+        let mut numbers = Vec::new();
+        for _ in 0..depth {
+            numbers.extend(&[1, 1]);
+        }
+        numbers.extend(&[0, 1, 1]);
+        numbers.extend(std::iter::repeat_n(1, depth));
+
+        let mut engine = Engine::new(numbers);
+        engine.read_node().unwrap();
+        assert_eq!(engine.nodes.len(), depth + 1);
+        assert_eq!(engine.metadata_sum(), depth as u32 + 1);
+        assert_eq!(engine.root_node_value(), 1);
+        assert!(engine.to_json().ends_with(&"]}".repeat(depth + 1)));
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut engine = Engine::new(Vec::new());
+        let root = engine.add_node(None, vec![1, 1, 2]);
+        engine.add_node(Some(root), vec![10, 11, 12]);
+        let child = engine.add_node(Some(root), vec![2]);
+        engine.add_node(Some(child), vec![99]);
+
+        let numbers: Vec<String> = engine.encode().iter().map(|x| x.to_string()).collect();
+        assert_eq!(numbers.join(" "), EXAMPLE);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut generator = Generator { seed: 8 };
+        for size in 1..200 {
+            let mut engine = random_tree(&mut generator, size);
+            let numbers = engine.encode();
+
+            let mut parsed = Engine::new(numbers.clone());
+            parsed.read_node().unwrap();
+            assert_eq!(parsed.nodes.len(), size);
+            assert_eq!(parsed.encode(), numbers);
+            assert_eq!(parsed.metadata_sum(), engine.metadata_sum());
+            assert_eq!(parsed.root_node_value(), engine.root_node_value());
+
+            let mut decoded = from_json(&engine.to_json()).unwrap();
+            assert_eq!(decoded.encode(), numbers);
+            assert_eq!(decoded.root_node_value(), engine.root_node_value());
+        }
+    }
+
+    #[test]
+    fn test_json_errors() {
+        assert!(from_json(" { \"children\" : [ ] , \"metadata\" : [ 4 , 2 ] } ").is_ok());
+        assert_eq!(
+            from_json("{\"metadata\":[1,],\"children\":[]}").err(),
+            Some(String::from("expected a number at byte 15"))
+        );
+        assert_eq!(
+            from_json("{\"parent\":0}").err(),
+            Some(String::from(
+                "expected \"metadata\" or \"children\" at byte 10"
+            ))
+        );
+        assert_eq!(
+            from_json("{\"children\":[{}]").err(),
+            Some(String::from("expected ',' or '}' at byte 16"))
+        );
+        assert_eq!(
+            from_json("{} {}").err(),
+            Some(String::from("expected the end of the input at byte 3"))
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        let engine = parse(EXAMPLE).unwrap();
+        assert_eq!(
+            engine.to_dot(),
+            "digraph license {
+    n0 [label=\"0: 1 1 2\"];
+    n1 [label=\"1: 10 11 12\"];
+    n2 [label=\"2: 2\"];
+    n3 [label=\"3: 99\"];
+    n0 -> n1;
+    n0 -> n2;
+    n2 -> n3;
+}
+"
+        );
+        assert_eq!(
+            engine.to_json(),
+            "{\"metadata\":[1,1,2],\"children\":[\
+             {\"metadata\":[10,11,12],\"children\":[]},\
+             {\"metadata\":[2],\"children\":[{\"metadata\":[99],\"children\":[]}]}]}"
+        );
+    }
 }
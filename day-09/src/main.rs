@@ -1,85 +1,450 @@
 use regex::Regex;
-use std::collections::VecDeque;
+use std::env;
 use std::io::{stdin, Read};
 
-fn solve(players: usize, last_marble: u32) -> u32 {
-    let mut circle: VecDeque<u32> = VecDeque::new();
-    circle.push_back(0);
-    let mut marble_number: u32 = 1;
-    let mut scores = vec![0; players];
-    let mut player = 0;
-
-    while marble_number <= last_marble {
-        if marble_number % 23 == 0 {
-            for _ in 0..6 {
-                let marble = circle.pop_back().unwrap();
-                circle.push_front(marble);
-            }
-            let current_marble = circle.pop_back().unwrap();
-            let removed_marble = circle.pop_back().unwrap();
-            scores[player] += marble_number + removed_marble;
-            circle.push_back(current_marble);
+// The marbles form a doubly linked list indexed by marble number, so each
+// move, insertion and removal takes constant time
+#[derive(Debug)]
+struct Circle {
+    clockwise: Vec<u32>,
+    counter_clockwise: Vec<u32>,
+    current: u32,
+    len: usize,
+}
+
+impl Circle {
+    fn new() -> Circle {
+        Circle {
+            clockwise: vec![0],
+            counter_clockwise: vec![0],
+            current: 0,
+            len: 1,
+        }
+    }
+
+    fn move_clockwise(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.current = self.clockwise[self.current as usize];
+        }
+    }
+
+    fn move_counter_clockwise(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.current = self.counter_clockwise[self.current as usize];
+        }
+    }
+
+    // Place the marble clockwise of the current marble, and make it current
+    fn insert(&mut self, marble: u32) {
+        let index = marble as usize;
+        if index >= self.clockwise.len() {
+            self.clockwise.resize(index + 1, 0);
+            self.counter_clockwise.resize(index + 1, 0);
+        }
+
+        let next = self.clockwise[self.current as usize];
+        self.clockwise[index] = next;
+        self.counter_clockwise[index] = self.current;
+        self.clockwise[self.current as usize] = marble;
+        self.counter_clockwise[next as usize] = marble;
+        self.current = marble;
+        self.len += 1;
+    }
+
+    // Take out the current marble, making the marble clockwise of it current
+    fn remove(&mut self) -> u32 {
+        let marble = self.current;
+        let next = self.clockwise[marble as usize];
+        let previous = self.counter_clockwise[marble as usize];
+        self.clockwise[previous as usize] = next;
+        self.counter_clockwise[next as usize] = previous;
+        self.current = next;
+        self.len -= 1;
+
+        marble
+    }
+
+    // The marbles in clockwise order, starting from the lowest numbered
+    // one, which is marble 0 unless the rules have it removed
+    fn marbles(&self) -> Vec<u32> {
+        let mut marbles = Vec::with_capacity(self.len);
+        let mut marble = self.current;
+        for _ in 0..self.len {
+            marbles.push(marble);
+            marble = self.clockwise[marble as usize];
+        }
+
+        let lowest = (0..marbles.len()).min_by_key(|&i| marbles[i]).unwrap_or(0);
+        marbles.rotate_left(lowest);
+
+        marbles
+    }
+}
+
+// Each marble numbered by a multiple of scoring_multiple is kept instead of
+// placed, along with the marble rewind places counter-clockwise
+#[derive(Debug, Clone, Copy)]
+struct GameRules {
+    scoring_multiple: u32,
+    rewind: usize,
+}
+
+impl Default for GameRules {
+    fn default() -> GameRules {
+        GameRules {
+            scoring_multiple: 23,
+            rewind: 7,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Game {
+    rules: GameRules,
+    circle: Circle,
+    scores: Vec<u64>,
+    last_marble: u32,
+}
+
+impl Game {
+    fn new(players: usize, rules: GameRules) -> Game {
+        assert!(players > 0, "At least one player is needed");
+        // Keeping every marble would empty the circle
+        assert!(
+            rules.scoring_multiple > 1,
+            "The scoring multiple must be more than one"
+        );
+
+        Game {
+            rules,
+            circle: Circle::new(),
+            scores: vec![0; players],
+            last_marble: 0,
+        }
+    }
+
+    // Play the given number of turns from the start
+    fn play(players: usize, turns: u32, rules: GameRules) -> Game {
+        let mut game = Game::new(players, rules);
+        for _ in 0..turns {
+            game.play_turn();
+        }
+
+        game
+    }
+
+    fn play_turn(&mut self) {
+        let marble = self.last_marble + 1;
+        let player = (marble as usize - 1) % self.scores.len();
+
+        if marble.is_multiple_of(self.rules.scoring_multiple) {
+            self.circle.move_counter_clockwise(self.rules.rewind);
+            let removed_marble = self.circle.remove();
+            self.scores[player] += u64::from(marble) + u64::from(removed_marble);
         } else {
-            let marble = circle.pop_front().unwrap();
-            circle.push_back(marble);
-            circle.push_back(marble_number);
+            self.circle.move_clockwise(1);
+            self.circle.insert(marble);
         }
-        marble_number += 1;
-        player = (player + 1) % players;
+        self.last_marble = marble;
     }
 
-    scores.into_iter().max().unwrap()
+    // The player who took the latest turn, if any
+    fn player(&self) -> Option<usize> {
+        match self.last_marble {
+            0 => None,
+            marble => Some((marble as usize - 1) % self.scores.len()),
+        }
+    }
+
+    // The index of the player with the highest score, the earliest player
+    // winning ties, and that score
+    fn winner(&self) -> (usize, u64) {
+        let mut winner = (0, self.scores[0]);
+        for (player, &score) in self.scores.iter().enumerate() {
+            if score > winner.1 {
+                winner = (player, score);
+            }
+        }
+
+        winner
+    }
+
+    // A line of the puzzle diagram, with the player numbered from 1 and
+    // the current marble in parentheses
+    fn diagram_line(&self) -> String {
+        let mut line: Vec<char> = match self.player() {
+            Some(player) => format!("[{}] ", player + 1).chars().collect(),
+            None => "[-] ".chars().collect(),
+        };
+
+        for marble in self.circle.marbles() {
+            let cell = format!("{:>2} ", marble);
+            if marble == self.circle.current {
+                // The parentheses take the places of the spaces either side
+                // of the digits, reaching into the previous cell if needed
+                let end = line.len() + cell.len() - 1;
+                let start = end - marble.to_string().len() - 1;
+                line.extend(cell.chars());
+                line[start] = '(';
+                line[end] = ')';
+            } else {
+                line.extend(cell.chars());
+            }
+        }
+
+        line.into_iter().collect::<String>().trim_end().to_string()
+    }
+}
+
+fn solve(players: usize, last_marble: u32, rules: GameRules) -> u64 {
+    Game::play(players, last_marble, rules).winner().1
 }
 
 fn main() {
-    let mut input = String::new();
-    stdin().read_to_string(&mut input).unwrap();
+    // The players and the last marble come from the puzzle input unless
+    // both --players and --marbles are given. Part 2 multiplies the last
+    // marble by --multiplier, and --scoring-multiple and --rewind change
+    // the rules for keeping marbles.
+    let mut players = None;
+    let mut last_marble = None;
+    let mut multiplier = 100;
+    let mut rules = GameRules::default();
+    let mut diagram = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || -> u32 {
+            args.next()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or_else(|| panic!("{} requires a number", arg))
+        };
+        match arg.as_str() {
+            "--players" => players = Some(value() as usize),
+            "--marbles" => last_marble = Some(value()),
+            "--multiplier" => multiplier = value(),
+            "--scoring-multiple" => rules.scoring_multiple = value(),
+            "--rewind" => rules.rewind = value() as usize,
+            // Print the circle after each of the first turns
+            "--diagram" => diagram = Some(value()),
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+
+    let (players, last_marble) = match (players, last_marble) {
+        (Some(players), Some(last_marble)) => (players, last_marble),
+        _ => {
+            let mut input = String::new();
+            stdin().read_to_string(&mut input).unwrap();
 
-    let rule = r"^(\d+) players; last marble is worth (\d+) points$";
-    let re = Regex::new(rule).unwrap();
+            let rule = r"^(\d+) players; last marble is worth (\d+) points$";
+            let re = Regex::new(rule).unwrap();
 
-    let captures = re.captures(&input.trim()).unwrap();
-    let players: usize = captures[1].parse().unwrap();
-    let last_marble: u32 = captures[2].parse().unwrap();
+            let captures = re.captures(input.trim()).unwrap();
+            (
+                players.unwrap_or_else(|| captures[1].parse().unwrap()),
+                last_marble.unwrap_or_else(|| captures[2].parse().unwrap()),
+            )
+        }
+    };
+
+    if let Some(turns) = diagram {
+        let mut game = Game::new(players, rules);
+        println!("{}", game.diagram_line());
+        for _ in 0..turns {
+            game.play_turn();
+            println!("{}", game.diagram_line());
+        }
+    }
 
-    let part1 = solve(players, last_marble);
+    let part1 = solve(players, last_marble, rules);
     println!("Part 1: the winning elf's score is {}", part1);
 
-    let part2 = solve(players, last_marble * 100);
-    println!("Part 1: the winning elf's score is {}", part2);
+    let last_marble = last_marble
+        .checked_mul(multiplier)
+        .expect("The last marble is too large");
+    let part2 = solve(players, last_marble, rules);
+    println!("Part 2: the winning elf's score is {}", part2);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const DIAGRAM: &str = "[-] (0)
+        [1]  0 (1)
+        [2]  0 (2) 1
+        [3]  0  2  1 (3)
+        [4]  0 (4) 2  1  3
+        [5]  0  4  2 (5) 1  3
+        [6]  0  4  2  5  1 (6) 3
+        [7]  0  4  2  5  1  6  3 (7)
+        [8]  0 (8) 4  2  5  1  6  3  7
+        [9]  0  8  4 (9) 2  5  1  6  3  7
+        [1]  0  8  4  9  2(10) 5  1  6  3  7
+        [2]  0  8  4  9  2 10  5(11) 1  6  3  7
+        [3]  0  8  4  9  2 10  5 11  1(12) 6  3  7
+        [4]  0  8  4  9  2 10  5 11  1 12  6(13) 3  7
+        [5]  0  8  4  9  2 10  5 11  1 12  6 13  3(14) 7
+        [6]  0  8  4  9  2 10  5 11  1 12  6 13  3 14  7(15)
+        [7]  0(16) 8  4  9  2 10  5 11  1 12  6 13  3 14  7 15
+        [8]  0 16  8(17) 4  9  2 10  5 11  1 12  6 13  3 14  7 15
+        [9]  0 16  8 17  4(18) 9  2 10  5 11  1 12  6 13  3 14  7 15
+        [1]  0 16  8 17  4 18  9(19) 2 10  5 11  1 12  6 13  3 14  7 15
+        [2]  0 16  8 17  4 18  9 19  2(20)10  5 11  1 12  6 13  3 14  7 15
+        [3]  0 16  8 17  4 18  9 19  2 20 10(21) 5 11  1 12  6 13  3 14  7 15
+        [4]  0 16  8 17  4 18  9 19  2 20 10 21  5(22)11  1 12  6 13  3 14  7 15
+        [5]  0 16  8 17  4 18(19) 2 20 10 21  5 22 11  1 12  6 13  3 14  7 15
+        [6]  0 16  8 17  4 18 19  2(24)20 10 21  5 22 11  1 12  6 13  3 14  7 15
+        [7]  0 16  8 17  4 18 19  2 24 20(25)10 21  5 22 11  1 12  6 13  3 14  7 15";
+
+    #[test]
+    fn test_diagram() {
+        let mut game = Game::new(9, GameRules::default());
+        for (turn, expected) in DIAGRAM.lines().enumerate() {
+            assert_eq!(game.diagram_line(), expected.trim(), "turn {}", turn);
+            game.play_turn();
+        }
+    }
+
+    #[test]
+    fn test_diagram_without_marble_0() {
+        // Every third marble is kept along with the one just before the
+        // current marble, which takes marble 0 on the third turn
+        let rules = GameRules {
+            scoring_multiple: 3,
+            rewind: 1,
+        };
+        let mut game = Game::new(2, rules);
+        let mut lines = Vec::new();
+        for _ in 0..7 {
+            game.play_turn();
+            lines.push(game.diagram_line());
+        }
+        assert_eq!(
+            lines,
+            vec![
+                "[1]  0 (1)",
+                "[2]  0 (2) 1",
+                "[1]  1 (2)",
+                "[2]  1 (4) 2",
+                "[1]  1  4  2 (5)",
+                "[2]  1  4 (5)",
+                "[1]  1 (7) 4  5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_game_state() {
+        let game = Game::play(9, 25, GameRules::default());
+        assert_eq!(game.player(), Some(6));
+        assert_eq!(game.winner(), (4, 32));
+        assert_eq!(game.scores, vec![0, 0, 0, 0, 32, 0, 0, 0, 0]);
+        assert_eq!(game.circle.current, 25);
+        assert_eq!(game.circle.marbles().len(), 24);
+
+        let game = Game::play(9, 0, GameRules::default());
+        assert_eq!(game.player(), None);
+        assert_eq!(game.winner(), (0, 0));
+        assert_eq!(game.circle.marbles(), vec![0]);
+    }
+
+    #[test]
+    fn test_circle_moves() {
+        let mut circle = Circle::new();
+        for marble in 1..=4 {
+            circle.insert(marble);
+        }
+        assert_eq!(circle.marbles(), vec![0, 1, 2, 3, 4]);
+
+        circle.move_counter_clockwise(2);
+        assert_eq!(circle.current, 2);
+        circle.move_clockwise(4);
+        assert_eq!(circle.current, 1);
+        assert_eq!(circle.remove(), 1);
+        assert_eq!(circle.current, 2);
+        assert_eq!(circle.marbles(), vec![0, 2, 3, 4]);
+    }
+
     #[test]
     fn test_9() {
-        assert_eq!(32, solve(9, 25));
+        assert_eq!(32, solve(9, 25, GameRules::default()));
     }
 
     #[test]
     fn test_10() {
-        assert_eq!(8317, solve(10, 1618));
+        assert_eq!(8317, solve(10, 1618, GameRules::default()));
     }
 
     #[test]
     fn test_13() {
-        assert_eq!(146373, solve(13, 7999));
+        assert_eq!(146373, solve(13, 7999, GameRules::default()));
     }
 
     #[test]
     fn test_17() {
-        assert_eq!(2764, solve(17, 1104));
+        assert_eq!(2764, solve(17, 1104, GameRules::default()));
     }
 
     #[test]
     fn test_21() {
-        assert_eq!(54718, solve(21, 6111));
+        assert_eq!(54718, solve(21, 6111, GameRules::default()));
     }
 
     #[test]
     fn test_30() {
-        assert_eq!(37305, solve(30, 5807));
+        assert_eq!(37305, solve(30, 5807, GameRules::default()));
+    }
+
+    // Play on a plain vector, shifting marbles on every insertion, and return
+    // the scores with the circle from its lowest numbered marble
+    fn reference(players: usize, last_marble: u32, rules: GameRules) -> (Vec<u64>, Vec<u32>) {
+        let mut circle = vec![0];
+        let mut current = 0;
+        let mut scores = vec![0; players];
+        for marble in 1..=last_marble {
+            if marble % rules.scoring_multiple == 0 {
+                let len = circle.len();
+                current = (current + len * rules.rewind - rules.rewind) % len;
+                let removed = circle.remove(current);
+                scores[(marble as usize - 1) % players] += u64::from(marble + removed);
+                current %= circle.len().max(1);
+            } else {
+                current = (current + 1) % circle.len() + 1;
+                circle.insert(current, marble);
+            }
+        }
+
+        let lowest = (0..circle.len()).min_by_key(|&i| circle[i]).unwrap();
+        circle.rotate_left(lowest);
+        (scores, circle)
+    }
+
+    #[test]
+    fn test_rules_against_reference() {
+        for &(scoring_multiple, rewind) in [(23, 7), (5, 2), (7, 0), (4, 11), (3, 1)].iter() {
+            let rules = GameRules {
+                scoring_multiple,
+                rewind,
+            };
+            for &players in [1, 2, 9, 13].iter() {
+                let game = Game::play(players, 2000, rules);
+                let (scores, circle) = reference(players, 2000, rules);
+                assert_eq!(game.scores, scores);
+                assert_eq!(game.circle.marbles(), circle);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scores_beyond_u32() {
+        // Two players keep every other marble, so scores pass u32::MAX well
+        // before the marbles do
+        let rules = GameRules {
+            scoring_multiple: 2,
+            rewind: 1,
+        };
+        let game = Game::play(2, 200_000, rules);
+        assert!(game.winner().1 > u64::from(u32::MAX));
+        assert_eq!(game.scores[0], 0);
     }
 }
@@ -1,7 +1,30 @@
 use regex::Regex;
+use std::env;
+use std::fs;
 use std::io::{stdin, Read};
 
-#[derive(Debug)]
+// The block letters of the message, each 6 pixels wide and 10 tall, with
+// 2 blank columns between letters
+const GLYPH_WIDTH: i64 = 6;
+const GLYPH_HEIGHT: i64 = 10;
+const GLYPH_SPACING: i64 = 2;
+
+// The letters of the font, laid out side by side just as in the sky
+const FONT_LETTERS: &str = "ABCEFGHJKLNPRXZ";
+const FONT: [&str; 10] = [
+    "..##..  #####.  .####.  ######  ######  .####.  #....#  ...###  #....#  #.....  #....#  #####.  #####.  #....#  ######",
+    ".#..#.  #....#  #....#  #.....  #.....  #....#  #....#  ....#.  #...#.  #.....  ##...#  #....#  #....#  #....#  .....#",
+    "#....#  #....#  #.....  #.....  #.....  #.....  #....#  ....#.  #..#..  #.....  ##...#  #....#  #....#  .#..#.  .....#",
+    "#....#  #....#  #.....  #.....  #.....  #.....  #....#  ....#.  #.#...  #.....  #.#..#  #....#  #....#  .#..#.  ....#.",
+    "#....#  #####.  #.....  #####.  #####.  #.....  ######  ....#.  ##....  #.....  #.#..#  #####.  #####.  ..##..  ...#..",
+    "######  #....#  #.....  #.....  #.....  #..###  #....#  ....#.  ##....  #.....  #..#.#  #.....  #..#..  ..##..  ..#...",
+    "#....#  #....#  #.....  #.....  #.....  #....#  #....#  ....#.  #.#...  #.....  #..#.#  #.....  #...#.  .#..#.  .#....",
+    "#....#  #....#  #.....  #.....  #.....  #....#  #....#  #...#.  #..#..  #.....  #...##  #.....  #...#.  .#..#.  #.....",
+    "#....#  #....#  #....#  #.....  #.....  #...##  #....#  #...#.  #...#.  #.....  #...##  #.....  #....#  #....#  #.....",
+    "#....#  #####.  .####.  ######  #.....  .###.#  #....#  .###..  #....#  ######  #....#  #.....  #....#  #....#  ######",
+];
+
+#[derive(Debug, Clone)]
 struct Point {
     x: i32,
     y: i32,
@@ -9,81 +32,242 @@ struct Point {
     dy: i32,
 }
 
+impl Point {
+    fn position_at(&self, seconds: i64) -> (i64, i64) {
+        (
+            i64::from(self.x) + i64::from(self.dx) * seconds,
+            i64::from(self.y) + i64::from(self.dy) * seconds,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    min_x: i64,
+    max_x: i64,
+    min_y: i64,
+    max_y: i64,
+}
+
+impl Bounds {
+    fn width(&self) -> i64 {
+        self.max_x - self.min_x + 1
+    }
+
+    fn height(&self) -> i64 {
+        self.max_y - self.min_y + 1
+    }
+}
+
 #[derive(Debug)]
 struct Sky {
     points: Vec<Point>,
-    min_x: i32,
-    max_x: i32,
-    min_y: i32,
-    max_y: i32,
-    width: i32,
-    height: i32,
+    seconds: i64,
 }
 
 impl Sky {
     fn new(points: Vec<Point>) -> Sky {
-        Sky {
-            points,
-            min_x: 0,
-            max_x: 0,
-            min_y: 0,
-            max_y: 0,
-            width: 0,
-            height: 0,
+        Sky { points, seconds: 0 }
+    }
+
+    fn positions_at(&self, seconds: i64) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.points
+            .iter()
+            .map(move |point| point.position_at(seconds))
+    }
+
+    fn positions(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.positions_at(self.seconds)
+    }
+
+    fn bounds_at(&self, seconds: i64) -> Bounds {
+        let mut bounds = Bounds {
+            min_x: i64::MAX,
+            max_x: i64::MIN,
+            min_y: i64::MAX,
+            max_y: i64::MIN,
+        };
+        for (x, y) in self.positions_at(seconds) {
+            bounds.min_x = bounds.min_x.min(x);
+            bounds.max_x = bounds.max_x.max(x);
+            bounds.min_y = bounds.min_y.min(y);
+            bounds.max_y = bounds.max_y.max(y);
         }
+
+        bounds
     }
 
-    fn tick(&mut self) {
-        self.min_x = i32::max_value();
-        self.max_x = i32::min_value();
-        self.min_y = i32::max_value();
-        self.max_y = i32::min_value();
-        for point in self.points.iter_mut() {
-            point.x += point.dx;
-            point.y += point.dy;
-            if point.x < self.min_x {
-                self.min_x = point.x;
+    fn bounds(&self) -> Bounds {
+        self.bounds_at(self.seconds)
+    }
+
+    // The earliest second at which the bounding box is smallest. The width
+    // and the height are each the spread of straight lines over time, which
+    // is convex, so the first second at which their sum stops shrinking is
+    // where the message appears.
+    fn message_time(&self) -> i64 {
+        if self.points.is_empty() {
+            return 0;
+        }
+
+        let size = |seconds: i64| {
+            let bounds = self.bounds_at(seconds);
+            bounds.width() + bounds.height()
+        };
+        let shrinking = |seconds: i64| size(seconds + 1) < size(seconds);
+
+        let mut high = 1;
+        while shrinking(high) {
+            high *= 2;
+        }
+        let mut low = 0;
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if shrinking(middle) {
+                low = middle + 1;
+            } else {
+                high = middle;
             }
-            if point.x > self.max_x {
-                self.max_x = point.x;
+        }
+
+        low
+    }
+
+    // Read the message at the current second, one glyph at a time from
+    // the left edge of the bounding box, giving '?' for unknown glyphs
+    fn read(&self) -> String {
+        let bounds = self.bounds();
+        if self.points.is_empty() || bounds.height() != GLYPH_HEIGHT {
+            return String::new();
+        }
+
+        let stride = GLYPH_WIDTH + GLYPH_SPACING;
+        let glyph_count = (bounds.width() + GLYPH_SPACING) / stride;
+        let mut pixels = vec![
+            vec![vec![false; GLYPH_WIDTH as usize]; GLYPH_HEIGHT as usize];
+            glyph_count as usize
+        ];
+        for (x, y) in self.positions() {
+            let (column, row) = (x - bounds.min_x, y - bounds.min_y);
+            let (glyph, offset) = (column / stride, column % stride);
+            if glyph < glyph_count && offset < GLYPH_WIDTH {
+                pixels[glyph as usize][row as usize][offset as usize] = true;
             }
-            if point.y < self.min_y {
-                self.min_y = point.y;
+        }
+
+        pixels
+            .iter()
+            .map(|glyph| {
+                FONT_LETTERS
+                    .chars()
+                    .enumerate()
+                    .find(|&(j, _)| {
+                        let offset = j * stride as usize;
+                        FONT.iter().zip(glyph.iter()).all(|(row, lit)| {
+                            row[offset..offset + GLYPH_WIDTH as usize]
+                                .chars()
+                                .zip(lit.iter())
+                                .all(|(c, &lit)| (c == '#') == lit)
+                        })
+                    })
+                    .map_or('?', |(_, letter)| letter)
+            })
+            .collect()
+    }
+
+    // Advance to the message and read it, returning the seconds waited
+    fn find_message(&mut self) -> (String, i64) {
+        self.seconds = self.message_time();
+        (self.read(), self.seconds)
+    }
+
+    // The pixels of the part of the sky inside the view at the current
+    // second, each point drawn as a square of scale by scale pixels. Far
+    // from the message the points spread over a huge area, so views with
+    // more than MAX_PIXELS pixels are refused rather than drawn.
+    fn render(&self, view: Bounds, scale: usize) -> Result<Vec<Vec<bool>>, String> {
+        const MAX_PIXELS: usize = 1 << 24;
+
+        let width = (view.width().max(0) as usize).saturating_mul(scale);
+        let height = (view.height().max(0) as usize).saturating_mul(scale);
+        if width.saturating_mul(height) > MAX_PIXELS {
+            return Err(format!(
+                "a view of {} by {} pixels is too large to draw, try --crop",
+                width, height
+            ));
+        }
+        let mut pixels = vec![vec![false; width]; height];
+
+        for (x, y) in self.positions() {
+            if x < view.min_x || x > view.max_x || y < view.min_y || y > view.max_y {
+                continue;
             }
-            if point.y > self.max_y {
-                self.max_y = point.y;
+            let column = (x - view.min_x) as usize * scale;
+            let row = (y - view.min_y) as usize * scale;
+            for line in pixels[row..row + scale].iter_mut() {
+                for pixel in line[column..column + scale].iter_mut() {
+                    *pixel = true;
+                }
             }
         }
-        self.width = (self.max_x - self.min_x).abs();
-        self.height = (self.max_y - self.min_y).abs();
+
+        Ok(pixels)
     }
 
-    fn display(&self) {
-        let mut area = vec![vec![false; self.max_x as usize + 1]; self.max_y as usize + 1];
+    // The view defaults to the bounding box of the points
+    fn view(&self, crop: Option<Bounds>) -> Option<Bounds> {
+        if self.points.is_empty() {
+            return crop;
+        }
+        Some(crop.unwrap_or_else(|| self.bounds()))
+    }
 
-        for point in self.points.iter() {
-            area[point.y as usize][point.x as usize] = true;
+    fn display(&self, crop: Option<Bounds>, scale: usize) -> Result<(), String> {
+        if let Some(view) = self.view(crop) {
+            for line in self.render(view, scale)?.iter() {
+                let line: String = line
+                    .iter()
+                    .map(|&lit| if lit { '#' } else { '.' })
+                    .collect();
+                println!("{}", line);
+            }
         }
 
-        for y in 0..self.max_y as usize + 1 {
-            for x in 0..self.max_x as usize + 1 {
-                print!("{}", if area[y][x] { '#' } else { '.' });
+        Ok(())
+    }
+
+    // A plain PBM image, keeping lines within the 70 characters the
+    // format asks for
+    fn to_pbm(&self, crop: Option<Bounds>, scale: usize) -> Result<String, String> {
+        let pixels = match self.view(crop) {
+            Some(view) => self.render(view, scale)?,
+            None => Vec::new(),
+        };
+        let width = pixels.first().map_or(0, |line| line.len());
+
+        let mut image = format!("P1\n{} {}\n", width, pixels.len());
+        for line in pixels.iter() {
+            for chunk in line.chunks(70) {
+                let bits: String = chunk
+                    .iter()
+                    .map(|&lit| if lit { '1' } else { '0' })
+                    .collect();
+                image.push_str(&bits);
+                image.push('\n');
             }
-            println!();
         }
+
+        Ok(image)
     }
 }
 
-fn main() {
-    let mut input = String::new();
-    stdin().read_to_string(&mut input).unwrap();
-
+fn parse(input: &str) -> Vec<Point> {
     let rule = r"^position=<\s*(-?\d+),\s*(-?\d+)> velocity=<\s*(-?\d+),\s*(-?\d+)>$";
     let re = Regex::new(rule).unwrap();
 
     let mut points = Vec::new();
     for line in input.trim().lines() {
-        if let Some(captures) = re.captures(line) {
+        if let Some(captures) = re.captures(line.trim()) {
             let x: i32 = captures[1].parse().unwrap();
             let y: i32 = captures[2].parse().unwrap();
             let dx: i32 = captures[3].parse().unwrap();
@@ -92,13 +276,258 @@ fn main() {
         }
     }
 
-    let mut sky = Sky::new(points);
+    points
+}
+
+fn main() -> Result<(), String> {
+    let mut input = String::new();
+    stdin().read_to_string(&mut input).unwrap();
+
+    let mut sky = Sky::new(parse(&input));
+
+    let (message, seconds) = sky.find_message();
+
+    // The sky is drawn at the message unless --seconds picks another time.
+    // --crop MIN_X,MIN_Y,MAX_X,MAX_Y limits the view, --scale enlarges each
+    // point, and --pbm writes the view as an image as well.
+    let mut crop = None;
+    let mut scale = 1;
+    let mut pbm = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || -> String {
+            args.next()
+                .unwrap_or_else(|| panic!("{} requires a value", arg))
+        };
+        match arg.as_str() {
+            "--seconds" => sky.seconds = value().parse().expect("--seconds requires a number"),
+            "--crop" => {
+                let corners: Vec<i64> = value()
+                    .split(',')
+                    .map(|x| x.parse().expect("--crop requires numbers"))
+                    .collect();
+                match corners[..] {
+                    [min_x, min_y, max_x, max_y] => {
+                        crop = Some(Bounds {
+                            min_x,
+                            max_x,
+                            min_y,
+                            max_y,
+                        })
+                    }
+                    _ => panic!("--crop requires MIN_X,MIN_Y,MAX_X,MAX_Y"),
+                }
+            }
+            "--scale" => scale = value().parse().expect("--scale requires a number"),
+            "--pbm" => pbm = Some(value()),
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+
+    println!("Part 1: the message is {}", message);
+    println!("Part 2: the message would appear after {} seconds", seconds);
+
+    sky.display(crop, scale)?;
+    if let Some(path) = pbm {
+        fs::write(path, sky.to_pbm(crop, scale)?).expect("Unable to write PBM image");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "position=< 9,  1> velocity=< 0,  2>
+        position=< 7,  0> velocity=<-1,  0>
+        position=< 3, -2> velocity=<-1,  1>
+        position=< 6, 10> velocity=<-2, -1>
+        position=< 2, -4> velocity=< 2,  2>
+        position=<-6, 10> velocity=< 2, -2>
+        position=< 1,  8> velocity=< 1, -1>
+        position=< 1,  7> velocity=< 1,  0>
+        position=<-3, 11> velocity=< 1, -2>
+        position=< 7,  6> velocity=<-1, -1>
+        position=<-2,  3> velocity=< 1,  0>
+        position=<-4,  3> velocity=< 2,  0>
+        position=<10, -3> velocity=<-1,  1>
+        position=< 5, 11> velocity=< 1, -2>
+        position=< 4,  7> velocity=< 0, -1>
+        position=< 8, -2> velocity=< 0,  1>
+        position=<15,  0> velocity=<-2,  0>
+        position=< 1,  6> velocity=< 1,  0>
+        position=< 8,  9> velocity=< 0, -1>
+        position=< 3,  3> velocity=<-1,  1>
+        position=< 0,  5> velocity=< 0, -1>
+        position=<-2,  2> velocity=< 2,  0>
+        position=< 5, -2> velocity=< 1,  2>
+        position=< 1,  4> velocity=< 2,  1>
+        position=<-2,  7> velocity=< 2, -2>
+        position=< 3,  6> velocity=<-1, -1>
+        position=< 5,  0> velocity=< 1,  0>
+        position=<-6,  0> velocity=< 2,  0>
+        position=< 5,  9> velocity=< 1, -2>
+        position=<14,  7> velocity=<-2,  0>
+        position=<-3,  6> velocity=< 2, -1>";
 
-    for j in 1..10500 {
-        sky.tick();
-        if sky.min_x >= 0 && sky.min_y >= 0 {
-            println!("j = {}", j);
-            sky.display();
+    // Lay out the message in the block font, then scatter the points by
+    // winding them back along assorted velocities
+    fn scattered(message: &str, seconds: i32) -> Vec<Point> {
+        let mut points = Vec::new();
+        for (j, letter) in message.chars().enumerate() {
+            let offset = FONT_LETTERS.find(letter).unwrap() * 8;
+            for (y, row) in FONT.iter().enumerate() {
+                for (x, c) in row[offset..offset + 6].chars().enumerate() {
+                    if c == '#' {
+                        let n = points.len() as i32;
+                        let (dx, dy) = (n % 7 - 3, (n * 5) % 9 - 4);
+                        let (x, y) = ((j * 8 + x) as i32 + 100, y as i32 - 40);
+                        points.push(Point {
+                            x: x - dx * seconds,
+                            y: y - dy * seconds,
+                            dx,
+                            dy,
+                        });
+                    }
+                }
+            }
         }
+        points
+    }
+
+    #[test]
+    fn test_example_time() {
+        let sky = Sky::new(parse(EXAMPLE));
+        assert_eq!(sky.message_time(), 3);
+        let bounds = sky.bounds_at(3);
+        assert_eq!((bounds.width(), bounds.height()), (10, 8));
+    }
+
+    #[test]
+    fn test_read_message() {
+        let alphabet = FONT_LETTERS;
+        let mut sky = Sky::new(scattered(alphabet, 10_454));
+        assert_eq!(sky.find_message(), (String::from(alphabet), 10_454));
+    }
+
+    #[test]
+    fn test_unknown_glyph() {
+        let mut points = scattered("LL", 0);
+        // Turn the second L into an unknown glyph by adding a pixel
+        points.push(Point {
+            x: 112,
+            y: -40,
+            dx: 0,
+            dy: 0,
+        });
+        let mut sky = Sky::new(points);
+        assert_eq!(sky.find_message(), (String::from("L?"), 0));
+
+        sky.seconds += 1;
+        assert_eq!(sky.read(), String::new());
+    }
+
+    #[test]
+    fn test_render_negative_coordinates() {
+        let points = vec![(-1_000_000, -5), (-999_998, -4), (-999_999, -3)]
+            .into_iter()
+            .map(|(x, y)| Point { x, y, dx: 0, dy: 0 })
+            .collect();
+        let sky = Sky::new(points);
+
+        let lines: Vec<String> = sky
+            .render(sky.bounds(), 1)
+            .unwrap()
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .map(|&lit| if lit { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        assert_eq!(lines, vec!["#..", "..#", ".#."]);
+
+        assert_eq!(
+            sky.to_pbm(None, 2).unwrap(),
+            "P1\n6 6\n110000\n110000\n000011\n000011\n001100\n001100\n"
+        );
+    }
+
+    #[test]
+    fn test_render_crop() {
+        let mut sky = Sky::new(parse(EXAMPLE));
+        sky.seconds = 3;
+        let crop = Bounds {
+            min_x: -1,
+            max_x: 4,
+            min_y: 2,
+            max_y: 4,
+        };
+        assert_eq!(
+            sky.to_pbm(Some(crop), 1).unwrap(),
+            "P1\n6 3\n010001\n011111\n010001\n"
+        );
+
+        // The view follows the points at any second
+        sky.seconds = 0;
+        let bounds = sky.bounds();
+        assert_eq!((bounds.min_x, bounds.min_y), (-6, -4));
+        assert_eq!(sky.render(bounds, 1).unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_pbm_line_length() {
+        let sky = Sky::new(vec![
+            Point {
+                x: 0,
+                y: 0,
+                dx: 0,
+                dy: 0,
+            },
+            Point {
+                x: 99,
+                y: 0,
+                dx: 0,
+                dy: 0,
+            },
+        ]);
+        let image = sky.to_pbm(None, 1).unwrap();
+        let lines: Vec<&str> = image.lines().collect();
+        assert_eq!(lines[1], "100 1");
+        assert_eq!(lines[2].len(), 70);
+        assert_eq!(lines[3].len(), 30);
+        assert!(lines[2].starts_with('1') && lines[3].ends_with('1'));
+        assert_eq!(Sky::new(Vec::new()).to_pbm(None, 1).unwrap(), "P1\n0 0\n");
+    }
+
+    #[test]
+    fn test_render_too_large() {
+        // Points far apart, as in a real input long before the message
+        let sky = Sky::new(vec![
+            Point {
+                x: -50_000,
+                y: -50_000,
+                dx: 0,
+                dy: 0,
+            },
+            Point {
+                x: 50_000,
+                y: 50_000,
+                dx: 0,
+                dy: 0,
+            },
+        ]);
+        assert!(sky.render(sky.bounds(), 1).is_err());
+        assert!(sky.to_pbm(None, 1).is_err());
+        assert!(sky.render(sky.bounds(), usize::MAX).is_err());
+
+        let crop = Bounds {
+            min_x: -50_000,
+            max_x: -49_990,
+            min_y: -50_000,
+            max_y: -49_990,
+        };
+        assert_eq!(sky.render(crop, 1).unwrap().len(), 11);
     }
 }
@@ -1,56 +1,262 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::env;
+use std::fs;
 use std::io::{stdin, Read};
+use std::thread;
 
-fn solve(serial_number: i32, min_dial: usize, max_dial: usize) -> ((usize, usize, usize)) {
-    let mut grid = Vec::new();
-
-    for y0 in 0..300 {
-        let mut row = Vec::new();
-        for x0 in 0..300 {
-            let rack_id = (x0 as i32 + 1) + 10;
-            let mut power_level = rack_id * (y0 as i32 + 1);
-            power_level += serial_number;
-            power_level *= rack_id;
-            power_level = (power_level / 100) % 10;
-            power_level -= 5;
-            row.push(power_level);
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Square {
+    x: usize,
+    y: usize,
+    dial: usize,
+    total_power: i64,
+}
+
+type Rank = (Reverse<i64>, usize, usize, usize);
+
+impl Square {
+    // Larger totals first, then the order the squares are swept in: by
+    // dial, then row, then column
+    fn rank(&self) -> Rank {
+        (Reverse(self.total_power), self.dial, self.y, self.x)
+    }
+
+    fn from_rank((Reverse(total_power), dial, y, x): Rank) -> Square {
+        Square {
+            x,
+            y,
+            dial,
+            total_power,
         }
-        grid.push(row);
     }
+}
+
+// The results of sweeping every square of one size across the grid
+#[derive(Debug)]
+struct DialSweep {
+    dial: usize,
+    // The largest total power among the squares with their top-left cell
+    // in each column
+    column_totals: Vec<i64>,
+    // The best squares of this size, best first
+    top_squares: Vec<Square>,
+}
 
-    let mut memoized_total_power = vec![vec![None; 300]; 300];
-    let mut largest_total_power = i32::min_value();
-    let mut solution = None;
+#[derive(Debug)]
+struct Grid {
+    width: usize,
+    height: usize,
+    // sums[y * (width + 1) + x] is the total power of the cells above and
+    // to the left of x,y, so a row and a column of zeros lead the table
+    sums: Vec<i64>,
+}
 
-    for dial in min_dial..=max_dial {
-        for y0 in 0..=(300 - dial) {
-            for x0 in 0..=(300 - dial) {
-                let mut total_power = 0;
-                if let Some(memo) = memoized_total_power[y0][x0] {
-                    total_power += memo;
-                    for yn in 0..(dial - 1) {
-                        total_power += grid[y0 + yn][x0 + dial - 1];
-                    }
-                    for xn in 0..dial {
-                        total_power += grid[y0 + dial - 1][x0 + xn];
-                    }
-                } else {
-                    for yn in 0..dial {
-                        for xn in 0..dial {
-                            total_power += grid[y0 + yn][x0 + xn];
-                        }
+impl Grid {
+    // The power function is given the 1-based X,Y coordinate of each cell
+    fn new<F>(width: usize, height: usize, power: F) -> Grid
+    where
+        F: Fn(usize, usize) -> i32,
+    {
+        let mut sums = vec![0; (width + 1) * (height + 1)];
+
+        for y0 in 0..height {
+            for x0 in 0..width {
+                let power_level = power(x0 + 1, y0 + 1);
+                sums[(y0 + 1) * (width + 1) + x0 + 1] = i64::from(power_level)
+                    + sums[y0 * (width + 1) + x0 + 1]
+                    + sums[(y0 + 1) * (width + 1) + x0]
+                    - sums[y0 * (width + 1) + x0];
+            }
+        }
+
+        Grid {
+            width,
+            height,
+            sums,
+        }
+    }
+
+    // The total power of the square with its top-left cell at the 1-based
+    // X,Y coordinate
+    fn total_power(&self, x: usize, y: usize, dial: usize) -> i64 {
+        let stride = self.width + 1;
+        let (x0, y0) = (x - 1, y - 1);
+        let (x1, y1) = (x0 + dial, y0 + dial);
+
+        self.sums[y1 * stride + x1] - self.sums[y0 * stride + x1] - self.sums[y1 * stride + x0]
+            + self.sums[y0 * stride + x0]
+    }
+
+    fn sweep_dial(&self, dial: usize, k: usize) -> DialSweep {
+        let mut column_totals = vec![i64::MIN; self.width - dial + 1];
+
+        // The worst of the squares kept so far is at the top of the heap
+        let mut heap: BinaryHeap<Rank> = BinaryHeap::new();
+        for y in 1..=(self.height - dial + 1) {
+            for x in 1..=(self.width - dial + 1) {
+                let square = Square {
+                    x,
+                    y,
+                    dial,
+                    total_power: self.total_power(x, y, dial),
+                };
+                column_totals[x - 1] = column_totals[x - 1].max(square.total_power);
+                if heap.len() < k {
+                    heap.push(square.rank());
+                } else if let Some(&worst) = heap.peek() {
+                    if square.rank() < worst {
+                        heap.pop();
+                        heap.push(square.rank());
                     }
                 }
-                memoized_total_power[y0][x0] = Some(total_power);
+            }
+        }
 
-                if total_power > largest_total_power {
-                    largest_total_power = total_power;
-                    solution = Some((x0 + 1, y0 + 1, dial));
-                }
+        DialSweep {
+            dial,
+            column_totals,
+            top_squares: heap
+                .into_sorted_vec()
+                .into_iter()
+                .map(Square::from_rank)
+                .collect(),
+        }
+    }
+
+    // Sweep each square size that fits in the grid, dealing the sizes out
+    // to the threads in turn so each gets a mix of large and small squares
+    fn sweep(&self, min_dial: usize, max_dial: usize, k: usize, threads: usize) -> Vec<DialSweep> {
+        let dials: Vec<usize> =
+            (min_dial.max(1)..=max_dial.min(self.width).min(self.height)).collect();
+        let threads = threads.clamp(1, dials.len().max(1));
+
+        let mut sweeps: Vec<DialSweep> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..threads)
+                .map(|t| {
+                    let dials = &dials;
+                    scope.spawn(move || {
+                        dials
+                            .iter()
+                            .skip(t)
+                            .step_by(threads)
+                            .map(|&dial| self.sweep_dial(dial, k))
+                            .collect::<Vec<DialSweep>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        sweeps.sort_by_key(|sweep| sweep.dial);
+
+        sweeps
+    }
+
+    // The k squares with the largest total power, best first, among all
+    // squares with sizes from min_dial to max_dial that fit in the grid.
+    // Ties go to the square a single sweep by dial, row and column would
+    // reach first, however many threads share the work.
+    fn top_squares(&self, min_dial: usize, max_dial: usize, k: usize) -> Vec<Square> {
+        self.top_squares_with_threads(min_dial, max_dial, k, available_threads())
+    }
+
+    fn top_squares_with_threads(
+        &self,
+        min_dial: usize,
+        max_dial: usize,
+        k: usize,
+        threads: usize,
+    ) -> Vec<Square> {
+        let mut ranks: Vec<Rank> = self
+            .sweep(min_dial, max_dial, k, threads)
+            .iter()
+            .flat_map(|sweep| sweep.top_squares.iter().map(Square::rank))
+            .collect();
+        ranks.sort_unstable();
+        ranks.truncate(k);
+
+        ranks.into_iter().map(Square::from_rank).collect()
+    }
+
+    // The power level of each cell, one row per line
+    fn power_heatmap(&self) -> String {
+        let mut levels = Vec::with_capacity(self.width * self.height);
+        for y in 1..=self.height {
+            for x in 1..=self.width {
+                levels.push(self.total_power(x, y, 1));
             }
         }
+
+        pgm(self.width, self.height, &levels)
     }
 
-    solution.unwrap()
+    // One row per square size from 1 down to the largest that fits, each
+    // showing the best total power of the squares with their top-left cell
+    // in each column. Columns too close to the right edge for the size are
+    // left at the lowest total.
+    fn dial_heatmap(&self) -> String {
+        let sweeps = self.sweep(1, self.width.min(self.height), 0, available_threads());
+        let lowest = sweeps
+            .iter()
+            .flat_map(|sweep| sweep.column_totals.iter())
+            .cloned()
+            .min()
+            .unwrap_or(0);
+
+        let mut totals = Vec::with_capacity(self.width * sweeps.len());
+        for sweep in sweeps.iter() {
+            totals.extend(sweep.column_totals.iter());
+            totals.extend((sweep.column_totals.len()..self.width).map(|_| lowest));
+        }
+
+        pgm(self.width, sweeps.len(), &totals)
+    }
+
+    // None when no square with a dial in the range fits on the grid
+    fn solve(&self, min_dial: usize, max_dial: usize) -> Option<Square> {
+        self.top_squares(min_dial, max_dial, 1).first().copied()
+    }
+}
+
+fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+// A plain PGM image, scaling the values so the lowest is black and the
+// highest is white
+fn pgm(width: usize, height: usize, values: &[i64]) -> String {
+    let lowest = values.iter().cloned().min().unwrap_or(0);
+    let highest = values.iter().cloned().max().unwrap_or(0);
+
+    let mut image = format!("P2\n{} {}\n255\n", width, height);
+    for row in values.chunks(width.max(1)) {
+        let shades: Vec<String> = row
+            .iter()
+            .map(|&value| match highest - lowest {
+                0 => 0,
+                range => (value - lowest) * 255 / range,
+            })
+            .map(|shade| shade.to_string())
+            .collect();
+        image.push_str(&shades.join(" "));
+        image.push('\n');
+    }
+
+    image
+}
+
+fn power_level(serial_number: i32, x: usize, y: usize) -> i32 {
+    let rack_id = x as i32 + 10;
+    let mut power_level = rack_id * y as i32;
+    power_level += serial_number;
+    power_level *= rack_id;
+    power_level = (power_level / 100) % 10;
+    power_level -= 5;
+
+    power_level
 }
 
 fn main() {
@@ -59,12 +265,214 @@ fn main() {
 
     let serial_number: i32 = input.trim().parse().unwrap();
 
-    let (x, y, _dial) = solve(serial_number, 3, 3);
-    println!("Part 1: the X,Y coordinate of the top-left fuel cell of the 3x3 square with the largest total power is {},{}", x, y);
+    // The grid size can be changed with --width and --height, --top lists
+    // the squares with the largest total power of any size, and --heatmap
+    // and --dial-heatmap write PGM images of the power landscape
+    let mut width = 300;
+    let mut height = 300;
+    let mut top = None;
+    let mut heatmap = None;
+    let mut dial_heatmap = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || -> String {
+            args.next()
+                .unwrap_or_else(|| panic!("{} requires a value", arg))
+        };
+        match arg.as_str() {
+            "--width" => width = value().parse().expect("--width requires a number"),
+            "--height" => height = value().parse().expect("--height requires a number"),
+            "--top" => top = Some(value().parse().expect("--top requires a number")),
+            "--heatmap" => heatmap = Some(value()),
+            "--dial-heatmap" => dial_heatmap = Some(value()),
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+
+    let grid = Grid::new(width, height, |x, y| power_level(serial_number, x, y));
+
+    match grid.solve(3, 3) {
+        Some(square) => println!("Part 1: the X,Y coordinate of the top-left fuel cell of the 3x3 square with the largest total power is {},{}", square.x, square.y),
+        None => println!("Part 1: no 3x3 square fits on the grid"),
+    }
+
+    match grid.solve(1, width.max(height)) {
+        Some(square) => println!(
+            "Part 2: the X,Y,size identifier of the square with the largest total power is {},{},{}",
+            square.x, square.y, square.dial
+        ),
+        None => println!("Part 2: no square fits on the grid"),
+    }
+
+    if let Some(k) = top {
+        for square in grid.top_squares(1, width.max(height), k) {
+            println!(
+                "{},{},{} has a total power of {}",
+                square.x, square.y, square.dial, square.total_power
+            );
+        }
+    }
+
+    if let Some(path) = heatmap {
+        fs::write(path, grid.power_heatmap()).expect("Unable to write heatmap");
+    }
+    if let Some(path) = dial_heatmap {
+        fs::write(path, grid.dial_heatmap()).expect("Unable to write dial heatmap");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puzzle_grid(serial_number: i32) -> Grid {
+        Grid::new(300, 300, |x, y| power_level(serial_number, x, y))
+    }
+
+    #[test]
+    fn test_power_levels() {
+        assert_eq!(power_level(8, 3, 5), 4);
+        assert_eq!(power_level(57, 122, 79), -5);
+        assert_eq!(power_level(39, 217, 196), 0);
+        assert_eq!(power_level(71, 101, 153), 4);
+    }
+
+    #[test]
+    fn test_part1_18() {
+        let square = puzzle_grid(18).solve(3, 3).unwrap();
+        assert_eq!((square.x, square.y, square.total_power), (33, 45, 29));
+    }
+
+    #[test]
+    fn test_part1_42() {
+        let square = puzzle_grid(42).solve(3, 3).unwrap();
+        assert_eq!((square.x, square.y, square.total_power), (21, 61, 30));
+    }
+
+    #[test]
+    fn test_no_square_fits() {
+        let grid = Grid::new(2, 2, |x, y| power_level(18, x, y));
+        assert!(grid.solve(3, 3).is_none());
+        assert_eq!(grid.solve(1, 2).map(|square| square.dial), Some(1));
+
+        let empty = Grid::new(0, 0, |x, y| power_level(18, x, y));
+        assert!(empty.solve(1, 1).is_none());
+    }
+
+    #[test]
+    fn test_part2_18() {
+        let square = puzzle_grid(18).solve(1, 300).unwrap();
+        assert_eq!(
+            (square.x, square.y, square.dial, square.total_power),
+            (90, 269, 16, 113)
+        );
+    }
+
+    #[test]
+    fn test_part2_42() {
+        let square = puzzle_grid(42).solve(1, 300).unwrap();
+        assert_eq!(
+            (square.x, square.y, square.dial, square.total_power),
+            (232, 251, 12, 119)
+        );
+    }
+
+    #[test]
+    fn test_total_power_against_brute_force() {
+        let power = |x: usize, y: usize| ((x * 7 + y * 11) % 9) as i32 - 4;
+        let grid = Grid::new(13, 7, power);
+
+        for dial in 1..=7 {
+            for y in 1..=(7 - dial + 1) {
+                for x in 1..=(13 - dial + 1) {
+                    let mut expected = 0;
+                    for yn in y..y + dial {
+                        for xn in x..x + dial {
+                            expected += i64::from(power(xn, yn));
+                        }
+                    }
+                    assert_eq!(grid.total_power(x, y, dial), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_top_squares() {
+        // A lone charged cell in a 4x3 grid, where every square covering it
+        // ties and the earliest swept wins
+        let grid = Grid::new(4, 3, |x, y| if (x, y) == (2, 2) { 5 } else { 0 });
+        let squares: Vec<(usize, usize, usize, i64)> = grid
+            .top_squares(1, 10, 6)
+            .iter()
+            .map(|s| (s.x, s.y, s.dial, s.total_power))
+            .collect();
+        assert_eq!(
+            squares,
+            vec![
+                (2, 2, 1, 5),
+                (1, 1, 2, 5),
+                (2, 1, 2, 5),
+                (1, 2, 2, 5),
+                (2, 2, 2, 5),
+                (1, 1, 3, 5),
+            ]
+        );
 
-    let (x, y, dial) = solve(serial_number, 1, 300);
-    println!(
-        "Part 2: the X,Y,size identifier of the square with the largest total power is {},{},{}",
-        x, y, dial
-    );
+        assert_eq!(grid.top_squares(1, 10, 100).len(), 12 + 6 + 2);
+    }
+
+    #[test]
+    fn test_threads_agree() {
+        // A flat grid ties every square of each size
+        let flat = Grid::new(20, 20, |_, _| 0);
+        // A striped grid ties squares across many sizes
+        let striped = Grid::new(31, 17, |x, _| if x % 2 == 0 { 1 } else { -1 });
+
+        for grid in [&flat, &striped, &puzzle_grid(42)].iter() {
+            let expected = grid.top_squares_with_threads(1, 300, 25, 1);
+            for &threads in [2, 3, 8, 1000].iter() {
+                assert_eq!(grid.top_squares_with_threads(1, 300, 25, threads), expected);
+            }
+        }
+
+        assert_eq!(
+            flat.top_squares_with_threads(1, 20, 3, 7)
+                .iter()
+                .map(|s| (s.x, s.y, s.dial))
+                .collect::<Vec<(usize, usize, usize)>>(),
+            vec![(1, 1, 1), (2, 1, 1), (3, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_heatmaps() {
+        let grid = Grid::new(3, 2, |x, y| (x * y) as i32);
+        assert_eq!(grid.power_heatmap(), "P2\n3 2\n255\n0 51 102\n51 153 255\n");
+
+        // The best single cells in each column are 2, 4 and 6, and the two
+        // 2x2 squares total 9 and 15, leaving the last column at the lowest
+        assert_eq!(grid.dial_heatmap(), "P2\n3 2\n255\n0 39 78\n137 255 0\n");
+    }
+
+    // Times sweeping every dial of a 1000x1000 grid with 1 to 8 threads
+    #[test]
+    #[ignore]
+    fn bench_threads() {
+        use std::time::Instant;
+
+        let grid = Grid::new(1000, 1000, |x, y| power_level(18, x, y));
+        for &threads in [1, 2, 4, 8].iter() {
+            let start = Instant::now();
+            let square = grid.top_squares_with_threads(1, 1000, 1, threads)[0];
+            println!(
+                "{} threads: {},{},{} in {:?}",
+                threads,
+                square.x,
+                square.y,
+                square.dial,
+                start.elapsed()
+            );
+        }
+    }
 }
@@ -1,141 +1,201 @@
 use pom::parser::*;
 use pom::Error;
 use std::collections::HashMap;
-use std::collections::VecDeque;
 use std::io::{stdin, Read};
 
-#[derive(Debug)]
-struct Pot {
-    number: i32,
-    plant: bool,
-}
+// The number of generations to simulate while looking for a repeat
+const MAX_GENERATIONS: u64 = 10_000;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Note {
     neighbors: Vec<bool>,
     next_generation: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Engine {
-    pots: VecDeque<Pot>,
-    note_hm: HashMap<Vec<bool>, bool>,
+    // Pot numbers of plants are offset plus the bit positions in plants,
+    // counting 64 pots per word, with the first plant always at bit zero
+    offset: i64,
+    plants: Vec<u64>,
+    // Bit n is set when a pot whose neighborhood LLCRR spells n in binary,
+    // L being the most significant bit, has a plant in the next generation
+    rules: u32,
 }
 
 impl Engine {
-    fn new(initial_state: Vec<bool>, notes: Vec<Note>) -> Engine {
-        let mut pots = VecDeque::new();
-        for (n, plant) in initial_state.into_iter().enumerate() {
-            pots.push_back(Pot {
-                number: n as i32,
-                plant,
-            });
-        }
-        let mut note_hm = HashMap::new();
+    // Plants growing in every empty pot would fill the infinite row, which
+    // can't be represented or summed
+    fn new(initial_state: Vec<bool>, notes: Vec<Note>) -> Result<Engine, &'static str> {
+        let mut rules = 0;
         for note in notes {
-            note_hm.insert(note.neighbors, note.next_generation);
+            if note.next_generation {
+                let index = note
+                    .neighbors
+                    .iter()
+                    .fold(0, |index, &plant| index << 1 | plant as usize);
+                rules |= 1 << index;
+            }
+        }
+        if rules & 1 == 1 {
+            return Err("Plants may not grow in every empty pot along the infinite row");
         }
 
-        Engine { pots, note_hm }
+        let mut engine = Engine {
+            offset: 0,
+            plants: Vec::new(),
+            rules,
+        };
+        engine.store(
+            0,
+            initial_state
+                .into_iter()
+                .map(|plant| plant as u64)
+                .collect::<Vec<u64>>()
+                .chunks(64)
+                .map(|chunk| chunk.iter().rev().fold(0, |word, &bit| word << 1 | bit))
+                .collect(),
+        );
+
+        Ok(engine)
     }
 
-    fn next_generation(&mut self) {
-        // Pad the queue with plantless pots to enable processing the end pots
-        let front_number = if let Some(pot) = self.pots.front() {
-            pot.number
-        } else {
-            unreachable!("Impossible if any plants remain");
-        };
-        let back_number = if let Some(pot) = self.pots.back() {
-            pot.number
-        } else {
-            unreachable!("Impossible if any plants remain");
-        };
-        for x in 1..=4 {
-            self.pots.push_front(Pot {
-                number: front_number - x,
-                plant: false,
-            });
-            self.pots.push_back(Pot {
-                number: back_number + x,
-                plant: false,
-            });
+    fn plant(&self, position: i64) -> u64 {
+        if position < 0 || position >= 64 * self.plants.len() as i64 {
+            return 0;
         }
+        (self.plants[(position / 64) as usize] >> (position % 64)) & 1
+    }
 
-        // Calculate next generation plant status for each non-edge pot.
-        // Note that j + 2 is the pot under consideration.
-        let mut new_pots = VecDeque::new();
-        for j in 0..self.pots.len() - 4 {
-            let mut neighbor_key = Vec::new();
-            for k in 0..5 {
-                neighbor_key.push(self.pots[j + k].plant);
+    // Shift the words so the first plant lands on bit zero, and drop any
+    // empty words at the end, so equal patterns always have equal words
+    fn store(&mut self, offset: i64, mut words: Vec<u64>) {
+        while words.last() == Some(&0) {
+            words.pop();
+        }
+        let first_word = match words.iter().position(|&word| word != 0) {
+            Some(first_word) => first_word,
+            None => {
+                self.offset = 0;
+                self.plants = Vec::new();
+                return;
             }
-            let plant = if let Some(plant) = self.note_hm.get(&neighbor_key) {
-                *plant
-            } else {
-                false
-            };
-            new_pots.push_back(Pot {
-                number: self.pots[j + 2].number,
-                plant,
+        };
+        let shift = words[first_word].trailing_zeros();
+
+        let mut plants = Vec::with_capacity(words.len() - first_word);
+        for j in first_word..words.len() {
+            let next = words.get(j + 1).cloned().unwrap_or(0);
+            plants.push(match shift {
+                0 => words[j],
+                _ => words[j] >> shift | next << (64 - shift),
             });
         }
+        while plants.last() == Some(&0) {
+            plants.pop();
+        }
 
-        // Remove plantless pots from the front of the queue
-        let pot_to_restore = loop {
-            if let Some(pot) = new_pots.pop_front() {
-                if pot.plant {
-                    break pot;
-                }
-            }
-        };
-        new_pots.push_front(pot_to_restore);
+        self.offset = offset + 64 * first_word as i64 + i64::from(shift);
+        self.plants = plants;
+    }
 
-        // Remove plantless pots from the back of the queue
-        let pot_to_restore = loop {
-            if let Some(pot) = new_pots.pop_back() {
-                if pot.plant {
-                    break pot;
-                }
+    fn next_generation(&mut self) {
+        if self.plants.is_empty() {
+            return;
+        }
+
+        // A plant can spread at most two pots beyond the current ones, so
+        // the new row starts two pots to the left. Bit k of the new row is
+        // then the pot centered in the window LLCRR ending at bit k of the
+        // current row, with one more word to catch the last two pots.
+        let mut words = vec![0; self.plants.len() + 1];
+        let mut window = 0;
+        for (j, word) in words.iter_mut().enumerate() {
+            let source = self.plants.get(j).cloned().unwrap_or(0);
+            if source == 0 && window == 0 {
+                continue;
             }
-        };
-        new_pots.push_back(pot_to_restore);
+            for k in 0..64 {
+                window = (window << 1 | (source >> k & 1) as usize) & 31;
+                *word |= u64::from(self.rules >> window & 1) << k;
+            }
+        }
 
-        self.pots = new_pots;
+        self.store(self.offset - 2, words);
     }
 
-    fn sum(&self) -> i32 {
+    fn sum(&self) -> i64 {
         let mut sum = 0;
 
-        for pot in self.pots.iter() {
-            if pot.plant {
-                sum += pot.number;
+        for (j, &word) in self.plants.iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                sum += self.offset + 64 * j as i64 + i64::from(word.trailing_zeros());
+                word &= word - 1;
             }
         }
 
         sum
     }
 
-    fn range(&self) -> (i32, i32) {
-        let front_number = if let Some(pot) = self.pots.front() {
-            pot.number
-        } else {
-            unreachable!("Impossible if any plants remain");
-        };
-        let back_number = if let Some(pot) = self.pots.back() {
-            pot.number
-        } else {
-            unreachable!("Impossible if any plants remain");
-        };
+    fn plant_count(&self) -> i64 {
+        self.plants
+            .iter()
+            .map(|word| i64::from(word.count_ones()))
+            .sum()
+    }
 
-        (front_number, back_number)
+    // The numbers of the first and last pots with plants, if any remain
+    fn range(&self) -> Option<(i64, i64)> {
+        let last = self.plants.last()?;
+        let back = 64 * (self.plants.len() as i64 - 1) + 63 - i64::from(last.leading_zeros());
+        Some((self.offset, self.offset + back))
+    }
+
+    // Once the pattern of plants repeats, possibly shifted along the row,
+    // every later generation repeats the same cycle shifted a little further
+    // each time around, so the sum can be extrapolated to any generation.
+    // Some rules keep growing the row forever, so give up with None if no
+    // pattern repeats within MAX_GENERATIONS.
+    fn sum_after(&self, generations: u64) -> Option<i64> {
+        let mut engine = self.clone();
+        let mut seen: HashMap<Vec<u64>, u64> = HashMap::new();
+        let mut history: Vec<(i64, i64, i64)> = Vec::new();
+
+        let mut generation = 0;
+        loop {
+            let front = engine.range().map_or(0, |(front, _)| front);
+
+            if let Some(&start) = seen.get(&engine.plants) {
+                let period = generation - start;
+                let shift = front - history[start as usize].0;
+                let cycles = ((generations - start) / period) as i64;
+                let phase = start + (generations - start) % period;
+                let (_, sum, plant_count) = history[phase as usize];
+                return Some(sum + cycles * shift * plant_count);
+            }
+
+            if generation == generations {
+                return Some(engine.sum());
+            }
+            if generation == MAX_GENERATIONS {
+                return None;
+            }
+
+            seen.insert(engine.plants.clone(), generation);
+            history.push((front, engine.sum(), engine.plant_count()));
+            engine.next_generation();
+            generation += 1;
+        }
     }
 
     fn pattern(&self) -> String {
         let mut pattern = String::new();
 
-        for pot in self.pots.iter() {
-            pattern.push(if pot.plant { '#' } else { '.' });
+        if let Some((front, back)) = self.range() {
+            for position in 0..=back - front {
+                pattern.push(if self.plant(position) == 1 { '#' } else { '.' });
+            }
         }
 
         pattern
@@ -143,14 +203,10 @@ impl Engine {
 
     #[allow(dead_code)]
     fn display(&self) {
-        for pot in self.pots.iter() {
-            print!("{} ", pot.number);
-        }
-        println!();
-        for pot in self.pots.iter() {
-            print!("{}", if pot.plant { "#" } else { "." });
+        if let Some((front, back)) = self.range() {
+            println!("{}..{}", front, back);
         }
-        println!();
+        println!("{}", self.pattern());
     }
 }
 
@@ -177,48 +233,362 @@ fn note<'a>() -> Parser<'a, u8, Note> {
 
 fn engine<'a>() -> Parser<'a, u8, Engine> {
     let notes = (space() * note()).repeat(1..);
-    (initial_state() + notes).map(|(initial_state, notes)| Engine::new(initial_state, notes))
+    (initial_state() + notes).convert(|(initial_state, notes)| Engine::new(initial_state, notes))
 }
 
 fn main() -> Result<(), Error> {
     let mut input = String::new();
     stdin().read_to_string(&mut input).unwrap();
 
-    let mut engine_p1 = engine().parse(input.as_bytes())?;
-    for _ in 0..20 {
-        engine_p1.next_generation();
+    let engine = engine().parse(input.as_bytes())?;
+
+    match engine.sum_after(20) {
+        Some(part1) => println!(
+            "Part 1: After 20 generations, the sum of the numbers of all pots which contain a plant is {}",
+            part1
+        ),
+        None => println!("Part 1: No pattern repeats within {} generations", MAX_GENERATIONS),
     }
-    let part1 = engine_p1.sum();
-    println!(
-        "Part 1: After 20 generations, the sum of the numbers of all pots which contain a plant is {}",
-        part1
-    );
 
-    let mut engine_p2 = engine().parse(input.as_bytes())?;
-    let mut patterns = HashMap::new();
+    match engine.sum_after(50_000_000_000) {
+        Some(part2) => println!(
+            "Part 2: After fifty billion generations, the sum of the numbers of all pots which contain a plant is {}",
+            part2
+        ),
+        None => println!("Part 2: No pattern repeats within {} generations", MAX_GENERATIONS),
+    }
 
-    // The following block of code allows the observation that the pattern
-    // of plants remains stable after the first 158 generations. For each
-    // subsequent generation, the pattern shifts to the right by one pot and the
-    // sum increases by 86.
-    for x in 0..200 {
-        if x > 157 {
-            let p = patterns.entry(engine_p2.pattern()).or_insert_with(Vec::new);
-            p.push((x, engine_p2.range(), engine_p2.sum()));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // The original implementation, kept to check the packed one against
+    #[derive(Debug, Clone)]
+    struct Pot {
+        number: i32,
+        plant: bool,
+    }
+
+    #[derive(Debug)]
+    struct Reference {
+        pots: VecDeque<Pot>,
+        note_hm: HashMap<Vec<bool>, bool>,
+    }
+
+    impl Reference {
+        fn new(initial_state: Vec<bool>, notes: Vec<Note>) -> Reference {
+            let mut pots = VecDeque::new();
+            for (n, plant) in initial_state.into_iter().enumerate() {
+                pots.push_back(Pot {
+                    number: n as i32,
+                    plant,
+                });
+            }
+            let mut note_hm = HashMap::new();
+            for note in notes {
+                note_hm.insert(note.neighbors, note.next_generation);
+            }
+
+            Reference { pots, note_hm }
         }
 
-        engine_p2.next_generation();
-        println!("[{}] sum = {}", x, engine_p2.sum());
-        engine_p2.display();
-        println!();
+        fn next_generation(&mut self) {
+            if self.pots.is_empty() {
+                return;
+            }
+
+            // Pad the queue with plantless pots to enable processing the end pots
+            let front_number = if let Some(pot) = self.pots.front() {
+                pot.number
+            } else {
+                unreachable!("Impossible if any plants remain");
+            };
+            let back_number = if let Some(pot) = self.pots.back() {
+                pot.number
+            } else {
+                unreachable!("Impossible if any plants remain");
+            };
+            for x in 1..=4 {
+                self.pots.push_front(Pot {
+                    number: front_number - x,
+                    plant: false,
+                });
+                self.pots.push_back(Pot {
+                    number: back_number + x,
+                    plant: false,
+                });
+            }
+
+            // Calculate next generation plant status for each non-edge pot.
+            // Note that j + 2 is the pot under consideration.
+            let mut new_pots = VecDeque::new();
+            for j in 0..self.pots.len() - 4 {
+                let mut neighbor_key = Vec::new();
+                for k in 0..5 {
+                    neighbor_key.push(self.pots[j + k].plant);
+                }
+                let plant = if let Some(plant) = self.note_hm.get(&neighbor_key) {
+                    *plant
+                } else {
+                    false
+                };
+                new_pots.push_back(Pot {
+                    number: self.pots[j + 2].number,
+                    plant,
+                });
+            }
+
+            if !new_pots.iter().any(|pot| pot.plant) {
+                self.pots = VecDeque::new();
+                return;
+            }
+
+            // Remove plantless pots from the front of the queue
+            let pot_to_restore = loop {
+                if let Some(pot) = new_pots.pop_front() {
+                    if pot.plant {
+                        break pot;
+                    }
+                }
+            };
+            new_pots.push_front(pot_to_restore);
+
+            // Remove plantless pots from the back of the queue
+            let pot_to_restore = loop {
+                if let Some(pot) = new_pots.pop_back() {
+                    if pot.plant {
+                        break pot;
+                    }
+                }
+            };
+            new_pots.push_back(pot_to_restore);
+
+            self.pots = new_pots;
+        }
+
+        fn sum(&self) -> i64 {
+            let mut sum = 0;
+
+            for pot in self.pots.iter() {
+                if pot.plant {
+                    sum += i64::from(pot.number);
+                }
+            }
+
+            sum
+        }
+
+        // The numbers of the first and last pots with plants, if any remain
+        fn range(&self) -> Option<(i32, i32)> {
+            match (self.pots.front(), self.pots.back()) {
+                (Some(front), Some(back)) => Some((front.number, back.number)),
+                _ => None,
+            }
+        }
+
+        fn pattern(&self) -> String {
+            let mut pattern = String::new();
+
+            for pot in self.pots.iter() {
+                pattern.push(if pot.plant { '#' } else { '.' });
+            }
+
+            pattern
+        }
+    }
+
+    fn parse(input: &str) -> (Vec<bool>, Vec<Note>) {
+        (initial_state() + (space() * note()).repeat(1..))
+            .parse(input.as_bytes())
+            .unwrap()
     }
-    println!("patterns = {:#?}", patterns);
 
-    let part2: u64 = (50_000_000_000 - 158) * 86 + 16002;
-    println!(
-        "Part 2: After fifty billion generations, the sum of the numbers of all pots which contain a plant is {}",
-        part2
-    );
+    fn assert_same(initial_state: Vec<bool>, notes: Vec<Note>, generations: usize) {
+        let mut engine = Engine::new(initial_state.clone(), notes.clone()).unwrap();
+        let mut reference = Reference::new(initial_state, notes);
+        // The reference only trims empty pots once it takes a step
+        for generation in 1..=generations {
+            engine.next_generation();
+            reference.next_generation();
+            assert_eq!(
+                engine.pattern(),
+                reference.pattern(),
+                "generation {}",
+                generation
+            );
+            assert_eq!(engine.sum(), reference.sum(), "generation {}", generation);
+            assert_eq!(
+                engine.range(),
+                reference
+                    .range()
+                    .map(|(front, back)| (i64::from(front), i64::from(back))),
+                "generation {}",
+                generation
+            );
+        }
+    }
 
-    Ok(())
+    const EXAMPLE: &str = "initial state: #..#.#..##......###...###
+
+...## => #
+..#.. => #
+.#... => #
+.#.#. => #
+.#.## => #
+.##.. => #
+.#### => #
+#.#.# => #
+#.### => #
+##.#. => #
+##.## => #
+###.. => #
+###.# => #
+####. => #";
+
+    fn simulated_sums(engine: &Engine, generations: usize) -> Vec<i64> {
+        let mut engine = engine.clone();
+        let mut sums = vec![engine.sum()];
+        for _ in 0..generations {
+            engine.next_generation();
+            sums.push(engine.sum());
+        }
+        sums
+    }
+
+    #[test]
+    fn test_against_reference() {
+        let (initial_state, notes) = parse(EXAMPLE);
+        assert_same(initial_state, notes, 500);
+
+        // Rows longer than a word of pots, with rules that spread plants
+        // in both directions, die out or drift along
+        let rule_sets = [
+            "..#.. => #\n.#... => #\n...#. => #",
+            "#.... => #\n....# => #\n.#.#. => #\n##.## => #\n..### => #",
+            "...## => #\n.##.. => #\n#.#.# => #\n.#.#. => #",
+            ".#... => #\n.##.. => #\n.###. => #",
+        ];
+        let initial_state = format!(
+            "initial state: {}#.#.#....#{}",
+            "#..#.#..##......###...###".repeat(3),
+            "##.###.#".repeat(10)
+        );
+        for rules in rule_sets.iter() {
+            let (initial_state, notes) = parse(&format!("{}\n\n{}", initial_state, rules));
+            assert_same(initial_state, notes, 300);
+        }
+    }
+
+    #[test]
+    fn test_part1() {
+        let engine = engine().parse(EXAMPLE.as_bytes()).unwrap();
+        assert_eq!(engine.sum_after(20), Some(325));
+    }
+
+    #[test]
+    fn test_sum_after_matches_simulation() {
+        let engine = engine().parse(EXAMPLE.as_bytes()).unwrap();
+        for (generation, &sum) in simulated_sums(&engine, 300).iter().enumerate() {
+            assert_eq!(engine.sum_after(generation as u64), Some(sum));
+        }
+    }
+
+    #[test]
+    fn test_glider() {
+        // A single plant which moves one pot to the right every generation
+        let engine = engine()
+            .parse("initial state: #\n\n.#... => #".as_bytes())
+            .unwrap();
+        assert_eq!(engine.sum_after(0), Some(0));
+        assert_eq!(engine.sum_after(7), Some(7));
+        assert_eq!(engine.sum_after(50_000_000_000), Some(50_000_000_000));
+    }
+
+    #[test]
+    fn test_two_generation_cycle() {
+        // A plant which alternately sprouts a second plant two pots to its
+        // right and then moves four pots along
+        let engine = engine()
+            .parse("initial state: #\n\n#.... => #\n..#.. => #".as_bytes())
+            .unwrap();
+        let sums = simulated_sums(&engine, 6);
+        assert_eq!(sums, vec![0, 2, 4, 10, 8, 18, 12]);
+        assert_eq!(engine.sum_after(50_000_000_000), Some(100_000_000_000));
+        assert_eq!(engine.sum_after(50_000_000_001), Some(200_000_000_002));
+    }
+
+    #[test]
+    fn test_all_plants_die() {
+        let engine = engine()
+            .parse("initial state: #.#\n\n..#.. => .".as_bytes())
+            .unwrap();
+        assert_eq!(engine.sum_after(1), Some(0));
+        assert_eq!(engine.sum_after(50_000_000_000), Some(0));
+    }
+
+    #[test]
+    fn test_plants_in_every_empty_pot() {
+        let input = "initial state: #\n\n..... => #\n..#.. => #";
+        assert!(engine().parse(input.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_no_repeat() {
+        // A pot grows exactly when its two neighbors differ, which draws an
+        // ever wider Sierpinski triangle that never repeats. It is symmetric
+        // about pot zero, so the sum stays zero.
+        let mut notes = String::new();
+        for index in 0..32 {
+            if (index >> 3 & 1) != (index >> 1 & 1) {
+                let neighbors: String = (0..5)
+                    .rev()
+                    .map(|bit| if index >> bit & 1 == 1 { '#' } else { '.' })
+                    .collect();
+                notes.push_str(&format!("{} => #\n", neighbors));
+            }
+        }
+        let input = format!("initial state: #\n\n{}", notes);
+        let engine = engine().parse(input.as_bytes()).unwrap();
+        assert_eq!(engine.sum_after(2), Some(0));
+        assert_eq!(engine.sum_after(MAX_GENERATIONS), Some(0));
+        assert_eq!(engine.sum_after(MAX_GENERATIONS + 1), None);
+        assert_eq!(engine.sum_after(50_000_000_000), None);
+    }
+
+    // Times 1000 generations of the example rules over 20000 pots, packed
+    // and unpacked
+    #[test]
+    #[ignore]
+    fn bench_generations() {
+        use std::time::Instant;
+
+        let (mut initial_state, notes) = parse(EXAMPLE);
+        initial_state = initial_state.repeat(800);
+
+        let mut reference = Reference::new(initial_state.clone(), notes.clone());
+        let start = Instant::now();
+        for _ in 0..1_000 {
+            reference.next_generation();
+        }
+        println!(
+            "Reference: 1000 generations of 20000 pots in {:?}",
+            start.elapsed()
+        );
+
+        let mut engine = Engine::new(initial_state, notes).unwrap();
+        let start = Instant::now();
+        for _ in 0..1_000 {
+            engine.next_generation();
+        }
+        println!(
+            "Packed: 1000 generations of 20000 pots in {:?}",
+            start.elapsed()
+        );
+
+        assert_eq!(engine.sum(), reference.sum());
+    }
 }
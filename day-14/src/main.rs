@@ -1,93 +1,186 @@
 use std::char;
+use std::env;
 use std::io::{stdin, Read};
-use std::u32;
 
+// Knuth-Morris-Pratt matcher fed one score at a time, so the scoreboard
+// never has to be rescanned
 #[derive(Debug)]
+struct Matcher {
+    pattern: Vec<u8>,
+    // Length of the longest proper prefix of pattern[..=j] that is also a suffix
+    borders: Vec<usize>,
+    matched: usize,
+}
+
+impl Matcher {
+    fn new(pattern: &[u8]) -> Matcher {
+        assert!(!pattern.is_empty(), "Cannot match an empty score sequence");
+
+        let mut borders = vec![0; pattern.len()];
+        let mut border = 0;
+        for j in 1..pattern.len() {
+            while border > 0 && pattern[j] != pattern[border] {
+                border = borders[border - 1];
+            }
+            if pattern[j] == pattern[border] {
+                border += 1;
+            }
+            borders[j] = border;
+        }
+
+        Matcher {
+            pattern: pattern.to_vec(),
+            borders,
+            matched: 0,
+        }
+    }
+
+    // Returns true when the score completes an occurrence of the pattern
+    fn feed(&mut self, score: u8) -> bool {
+        while self.matched > 0
+            && (self.matched == self.pattern.len() || self.pattern[self.matched] != score)
+        {
+            self.matched = self.borders[self.matched - 1];
+        }
+        if self.pattern[self.matched] == score {
+            self.matched += 1;
+        }
+
+        self.matched == self.pattern.len()
+    }
+}
+
+#[derive(Debug, Clone)]
 struct Engine {
-    board: Vec<u32>,
-    elf_a: usize,
-    elf_b: usize,
+    board: Vec<u8>,
+    elves: Vec<usize>,
 }
 
 impl Engine {
     fn new() -> Engine {
-        Engine {
-            board: vec![3, 7],
-            elf_a: 0,
-            elf_b: 1,
-        }
+        Engine::with_elves(vec![3, 7], vec![0, 1])
     }
 
-    fn generate_new_recipes(&mut self) -> Vec<u32> {
-        let mut new_recipes = Vec::new();
+    // Each elf starts on the recipe at the given position of the scoreboard
+    fn with_elves(board: Vec<u8>, elves: Vec<usize>) -> Engine {
+        assert!(
+            board.iter().all(|&x| x < 10),
+            "Scores must be single digits"
+        );
+        assert!(!elves.is_empty(), "At least one elf must be making recipes");
+        assert!(
+            elves.iter().all(|&elf| elf < board.len()),
+            "Every elf must start on a recipe on the scoreboard"
+        );
 
-        let sum = self.board[self.elf_a] + self.board[self.elf_b];
-        if sum >= 10 {
-            new_recipes.push(1);
-        }
-        new_recipes.push(sum % 10);
+        Engine { board, elves }
+    }
 
-        new_recipes
+    // The sum of the current recipes is split into its digits, most
+    // significant first, and each becomes a new recipe
+    fn generate_new_recipes(&mut self) {
+        let mut sum: usize = self
+            .elves
+            .iter()
+            .map(|&elf| usize::from(self.board[elf]))
+            .sum();
+
+        let start = self.board.len();
+        loop {
+            self.board.push((sum % 10) as u8);
+            sum /= 10;
+            if sum == 0 {
+                break;
+            }
+        }
+        self.board[start..].reverse();
     }
 
     fn set_current_recipes(&mut self) {
-        self.elf_a = (self.elf_a + 1 + self.board[self.elf_a] as usize) % self.board.len();
-        self.elf_b = (self.elf_b + 1 + self.board[self.elf_b] as usize) % self.board.len();
+        for elf in self.elves.iter_mut() {
+            *elf = (*elf + 1 + usize::from(self.board[*elf])) % self.board.len();
+        }
     }
 
     fn solve_part1(&mut self, recipe_count: usize) -> String {
-        for _ in 0..(recipe_count + 10) {
-            let mut new_recipes = self.generate_new_recipes();
-            self.board.append(&mut new_recipes);
+        while self.board.len() < recipe_count + 10 {
+            self.generate_new_recipes();
             self.set_current_recipes();
             //self.display();
         }
 
         self.board[recipe_count..(recipe_count + 10)]
             .iter()
-            .map(|&x| char::from_digit(x, 10).unwrap())
+            .map(|&x| char::from_digit(u32::from(x), 10).unwrap())
             .collect()
     }
 
-    fn solve_part2(&mut self, score_sequence: &[u32]) -> usize {
-        loop {
-            for recipe in self.generate_new_recipes() {
-                //self.display();
-                self.board.push(recipe);
-                if self.board.len() < score_sequence.len() {
-                    continue;
-                }
-                let left_recipe_count = self.board.len() - score_sequence.len();
-                let mut discrepancy_flag = false;
-                for j in 0..score_sequence.len() {
-                    if score_sequence[j] != self.board[left_recipe_count + j] {
-                        discrepancy_flag = true;
-                        break;
-                    }
-                }
-                if !discrepancy_flag {
-                    return left_recipe_count;
+    // Other scoreboards may never produce the sequence, so give up after
+    // `recipe_limit` recipes
+    fn solve_part2(&mut self, score_sequence: &[u8], recipe_limit: usize) -> Option<usize> {
+        self.search(&[score_sequence.to_vec()], 1, recipe_limit)[0]
+            .first()
+            .cloned()
+    }
+
+    // For each pattern, the number of recipes to the left of each of its
+    // first `limit` occurrences, searching only the first `recipe_limit`
+    // recipes so patterns that never appear cannot loop forever
+    fn search(
+        &mut self,
+        patterns: &[Vec<u8>],
+        limit: usize,
+        recipe_limit: usize,
+    ) -> Vec<Vec<usize>> {
+        let mut matchers: Vec<Matcher> = patterns.iter().map(|p| Matcher::new(p)).collect();
+        let mut occurrences = vec![Vec::new(); patterns.len()];
+        if limit == 0 {
+            return occurrences;
+        }
+
+        let mut position = 0;
+        while position < recipe_limit {
+            if position == self.board.len() {
+                self.generate_new_recipes();
+                self.set_current_recipes();
+            }
+
+            let score = self.board[position];
+            position += 1;
+
+            let mut done = true;
+            for (matcher, found) in matchers.iter_mut().zip(occurrences.iter_mut()) {
+                if found.len() < limit && matcher.feed(score) {
+                    found.push(position - matcher.pattern.len());
                 }
+                done &= found.len() == limit;
+            }
+            if done {
+                break;
             }
-            self.set_current_recipes();
         }
+
+        occurrences
     }
 
     #[allow(dead_code)]
     fn display(&self) {
+        const BRACKETS: [(char, char); 4] = [('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+
         for (j, recipe) in self.board.iter().enumerate() {
-            if self.elf_a == j {
-                print!("(");
-            }
-            if self.elf_b == j {
-                print!("[");
+            let current: Vec<(char, char)> = self
+                .elves
+                .iter()
+                .enumerate()
+                .filter(|(_, &elf)| elf == j)
+                .map(|(k, _)| BRACKETS[k % BRACKETS.len()])
+                .collect();
+            for (open, _) in current.iter() {
+                print!("{}", open);
             }
             print!("{}", recipe);
-            if self.elf_b == j {
-                print!("]");
-            }
-            if self.elf_a == j {
-                print!(")");
+            for (_, close) in current.iter().rev() {
+                print!("{}", close);
             }
             print!(" ");
         }
@@ -99,7 +192,55 @@ fn main() {
     let mut input = String::new();
     stdin().read_to_string(&mut input).unwrap();
 
-    let mut engine = Engine::new();
+    // Further score sequences given as arguments are searched together,
+    // listing up to --limit occurrences within the first --recipes recipes.
+    // --scoreboard sets the starting recipes and --elves the comma-separated
+    // starting positions, with one elf per starting recipe by default
+    let mut patterns = Vec::new();
+    let mut limit = 10;
+    let mut recipe_limit = 100_000_000;
+    let mut board = None;
+    let mut elves = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--limit" => {
+                limit = args
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .expect("--limit requires a number")
+            }
+            "--recipes" => {
+                recipe_limit = args
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .expect("--recipes requires a number")
+            }
+            "--scoreboard" => {
+                board = Some(digits(&args.next().expect("--scoreboard requires scores")));
+            }
+            "--elves" => {
+                elves = Some(
+                    args.next()
+                        .expect("--elves requires positions")
+                        .split(',')
+                        .map(|x| x.parse().expect("Elf positions must be numbers"))
+                        .collect(),
+                )
+            }
+            _ => patterns.push(arg),
+        }
+    }
+    let initial = match (board, elves) {
+        (None, None) => Engine::new(),
+        (board, elves) => {
+            let board = board.unwrap_or_else(|| Engine::new().board);
+            let elves = elves.unwrap_or_else(|| (0..board.len()).collect());
+            Engine::with_elves(board, elves)
+        }
+    };
+
+    let mut engine = initial.clone();
     let recipe_count: usize = input.trim().parse().unwrap();
     let part1 = engine.solve_part1(recipe_count);
     println!(
@@ -107,18 +248,41 @@ fn main() {
         recipe_count, part1
     );
 
-    let mut engine = Engine::new();
-    let score_sequence: Vec<u32> = input
-        .trim()
+    let mut engine = initial.clone();
+    let score_sequence = digits(input.trim());
+    match engine.solve_part2(&score_sequence, recipe_limit) {
+        Some(part2) => println!(
+            "Part 2: {} recipes appear on the scoreboard to the left of '{}'",
+            part2,
+            input.trim()
+        ),
+        None => println!(
+            "Part 2: '{}' does not appear in the first {} recipes",
+            input.trim(),
+            recipe_limit
+        ),
+    }
+
+    if !patterns.is_empty() {
+        let mut engine = initial;
+        let sequences: Vec<Vec<u8>> = patterns.iter().map(|x| digits(x)).collect();
+        let occurrences = engine.search(&sequences, limit, recipe_limit);
+        for (pattern, found) in patterns.iter().zip(occurrences.iter()) {
+            let positions: Vec<String> = found.iter().map(|x| x.to_string()).collect();
+            println!(
+                "'{}' appears after {} recipes",
+                pattern,
+                positions.join(", ")
+            );
+        }
+    }
+}
+
+fn digits(input: &str) -> Vec<u8> {
+    input
         .chars()
-        .map(|x| x.to_digit(10).unwrap())
-        .collect();
-    let part2 = engine.solve_part2(&score_sequence);
-    println!(
-        "Part 2: {} recipes appear on the scoreboard to the left of '{}'",
-        part2,
-        input.trim()
-    );
+        .map(|x| x.to_digit(10).expect("Score sequences must be digits") as u8)
+        .collect()
 }
 
 #[cfg(test)]
@@ -153,27 +317,127 @@ mod tests {
     fn test_part2_51589() {
         let mut engine = Engine::new();
         let score_sequence = vec![5, 1, 5, 8, 9];
-        assert_eq!(engine.solve_part2(&score_sequence), 9);
+        assert_eq!(engine.solve_part2(&score_sequence, usize::MAX), Some(9));
     }
 
     #[test]
     fn test_part2_01245() {
         let mut engine = Engine::new();
         let score_sequence = vec![0, 1, 2, 4, 5];
-        assert_eq!(engine.solve_part2(&score_sequence), 5);
+        assert_eq!(engine.solve_part2(&score_sequence, usize::MAX), Some(5));
     }
 
     #[test]
     fn test_part2_92510() {
         let mut engine = Engine::new();
         let score_sequence = vec![9, 2, 5, 1, 0];
-        assert_eq!(engine.solve_part2(&score_sequence), 18);
+        assert_eq!(engine.solve_part2(&score_sequence, usize::MAX), Some(18));
     }
 
     #[test]
     fn test_part2_59414() {
         let mut engine = Engine::new();
         let score_sequence = vec![5, 9, 4, 1, 4];
-        assert_eq!(engine.solve_part2(&score_sequence), 2018);
+        assert_eq!(engine.solve_part2(&score_sequence, usize::MAX), Some(2018));
+    }
+
+    #[test]
+    fn test_matcher_overlapping() {
+        let mut matcher = Matcher::new(&[1, 0, 1, 0, 1]);
+        let ends: Vec<usize> = [1, 0, 1, 0, 1, 0, 1, 1, 0, 1, 0, 1]
+            .iter()
+            .enumerate()
+            .filter(|(_, &score)| matcher.feed(score))
+            .map(|(j, _)| j)
+            .collect();
+        assert_eq!(ends, vec![4, 6, 11]);
+    }
+
+    #[test]
+    fn test_search_several_patterns() {
+        let mut engine = Engine::new();
+        let patterns = vec![
+            vec![5, 1, 5, 8, 9],
+            vec![1, 0],
+            vec![3, 7],
+            vec![9, 9, 9, 9, 9, 9, 9],
+        ];
+        let occurrences = engine.search(&patterns, 3, 2000);
+
+        let board = &engine.board;
+        for (pattern, found) in patterns.iter().zip(occurrences.iter()) {
+            let expected: Vec<usize> = (0..=2000 - pattern.len())
+                .filter(|&j| board[j..j + pattern.len()] == pattern[..])
+                .take(3)
+                .collect();
+            assert_eq!(found, &expected);
+        }
+        assert_eq!(occurrences[0], vec![9]);
+        assert_eq!(occurrences[1], vec![2, 4, 21]);
+        assert_eq!(occurrences[2], vec![0, 145, 210]);
+        assert!(occurrences[3].is_empty());
+    }
+
+    #[test]
+    fn test_two_elves_special_case() {
+        let mut engine = Engine::with_elves(vec![3, 7], vec![0, 1]);
+        assert_eq!(engine.solve_part1(2018), String::from("5941429882"));
+        let mut engine = Engine::with_elves(vec![3, 7], vec![0, 1]);
+        assert_eq!(engine.solve_part2(&[5, 9, 4, 1, 4], usize::MAX), Some(2018));
+    }
+
+    #[test]
+    fn test_three_elves() {
+        // 9 + 9 + 9 = 27, then each elf steps ten places around the five
+        // recipes and lands back where it started
+        let mut engine = Engine::with_elves(vec![9, 9, 9], vec![0, 1, 2]);
+        engine.generate_new_recipes();
+        engine.set_current_recipes();
+        assert_eq!(engine.board, vec![9, 9, 9, 2, 7]);
+        assert_eq!(engine.elves, vec![0, 1, 2]);
+        engine.generate_new_recipes();
+        assert_eq!(engine.board, vec![9, 9, 9, 2, 7, 2, 7]);
+    }
+
+    #[test]
+    fn test_many_digit_sums() {
+        // Twelve elves on nines make 108, a three-digit sum
+        let mut engine = Engine::with_elves(vec![9; 12], (0..12).collect());
+        engine.generate_new_recipes();
+        assert_eq!(engine.board[12..], [1, 0, 8]);
+
+        // A lone elf on a zero makes a single zero recipe
+        let mut engine = Engine::with_elves(vec![0], vec![0]);
+        assert_eq!(engine.solve_part1(0), String::from("0000000000"));
+    }
+
+    #[test]
+    fn test_shared_starting_recipe() {
+        let mut engine = Engine::with_elves(vec![3, 7], vec![1, 1]);
+        engine.generate_new_recipes();
+        engine.set_current_recipes();
+        assert_eq!(engine.board, vec![3, 7, 1, 4]);
+        assert_eq!(engine.elves, vec![1, 1]);
+    }
+
+    // Times searching 200 million recipes for three patterns at once
+    #[test]
+    #[ignore]
+    fn bench_search() {
+        use std::time::Instant;
+
+        let patterns = vec![
+            vec![0, 4, 7, 8, 0, 1],
+            vec![9, 9, 9, 9, 9, 9, 9, 9],
+            vec![2, 0, 1, 8],
+        ];
+        let start = Instant::now();
+        let mut engine = Engine::new();
+        let occurrences = engine.search(&patterns, usize::MAX, 200_000_000);
+        println!(
+            "200000000 recipes: {:?} occurrences in {:?}",
+            occurrences.iter().map(|x| x.len()).collect::<Vec<usize>>(),
+            start.elapsed()
+        );
     }
 }
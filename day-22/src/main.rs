@@ -2,6 +2,9 @@ use pom::parser::*;
 use pom::Error;
 use std::cmp::Reverse;
 use std::collections::{BinaryHeap, HashMap};
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
 use std::io::{stdin, Read};
 use std::str;
 
@@ -54,33 +57,154 @@ enum Equipment {
     Neither,
 }
 
+impl Equipment {
+    fn description(self) -> &'static str {
+        match self {
+            Equipment::Torch => "the torch",
+            Equipment::ClimbingGear => "the climbing gear",
+            Equipment::Neither => "neither tool",
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Equipment::Torch => 't',
+            Equipment::ClimbingGear => 'c',
+            Equipment::Neither => 'n',
+        }
+    }
+}
+
+// The most minutes a move or switch may take; the cave is scanned further
+// beyond the target the longer switching takes compared to moving
+const MAX_MINUTES: u32 = 1000;
+
+#[derive(Debug, Clone)]
+struct Rules {
+    x_multiplier: u64,
+    y_multiplier: u64,
+    erosion_modulo: u64,
+    move_minutes: u32,
+    switch_minutes: u32,
+    rocky: Vec<Equipment>,
+    wet: Vec<Equipment>,
+    narrow: Vec<Equipment>,
+    start_equipment: Equipment,
+    target_equipment: Equipment,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        use self::Equipment::*;
+
+        Rules {
+            x_multiplier: 16807,
+            y_multiplier: 48271,
+            erosion_modulo: 20183,
+            move_minutes: 1,
+            switch_minutes: 7,
+            rocky: vec![Torch, ClimbingGear],
+            wet: vec![ClimbingGear, Neither],
+            narrow: vec![Torch, Neither],
+            start_equipment: Torch,
+            target_equipment: Torch,
+        }
+    }
+}
+
+impl Rules {
+    fn allowed(&self, region_type: RegionType) -> &[Equipment] {
+        match region_type {
+            RegionType::Rocky => &self.rocky,
+            RegionType::Wet => &self.wet,
+            RegionType::Narrow => &self.narrow,
+        }
+    }
+
+    fn allows(&self, region_type: RegionType, equipment: Equipment) -> bool {
+        self.allowed(region_type).contains(&equipment)
+    }
+
+    // Multipliers and the modulo are kept to 32 bits so erosion levels can
+    // be multiplied together in a u64
+    fn apply(mut self, setting: Setting) -> Result<Rules, String> {
+        let bounded = |name: &str, value: usize, min: u32, max: u32| match u32::try_from(value) {
+            Ok(value) if (min..=max).contains(&value) => Ok(value),
+            _ => Err(format!("{} must be from {} to {}", name, min, max)),
+        };
+
+        match setting {
+            Setting::XMultiplier(value) => {
+                self.x_multiplier = u64::from(bounded("x multiplier", value, 0, u32::MAX)?)
+            }
+            Setting::YMultiplier(value) => {
+                self.y_multiplier = u64::from(bounded("y multiplier", value, 0, u32::MAX)?)
+            }
+            Setting::ErosionModulo(value) => {
+                self.erosion_modulo = u64::from(bounded("erosion modulo", value, 1, u32::MAX)?)
+            }
+            Setting::MoveMinutes(value) => {
+                self.move_minutes = bounded("move minutes", value, 0, MAX_MINUTES)?
+            }
+            Setting::SwitchMinutes(value) => {
+                self.switch_minutes = bounded("switch minutes", value, 0, MAX_MINUTES)?
+            }
+            Setting::Allowed(RegionType::Rocky, equipment) => self.rocky = equipment,
+            Setting::Allowed(RegionType::Wet, equipment) => self.wet = equipment,
+            Setting::Allowed(RegionType::Narrow, equipment) => self.narrow = equipment,
+            Setting::StartEquipment(equipment) => self.start_equipment = equipment,
+            Setting::TargetEquipment(equipment) => self.target_equipment = equipment,
+        }
+
+        Ok(self)
+    }
+}
+
+#[derive(Debug)]
+enum Setting {
+    XMultiplier(usize),
+    YMultiplier(usize),
+    ErosionModulo(usize),
+    MoveMinutes(usize),
+    SwitchMinutes(usize),
+    Allowed(RegionType, Vec<Equipment>),
+    StartEquipment(Equipment),
+    TargetEquipment(Equipment),
+}
+
 #[derive(Debug)]
 struct Scanner {
-    depth: u32,
     target: Coordinate,
-    erosion_levels: Vec<Vec<u32>>,
+    rules: Rules,
+    erosion_levels: Vec<Vec<u64>>,
 }
 
 impl Scanner {
-    fn new(depth: u32, target: Coordinate) -> Scanner {
-        let mut erosion_levels: Vec<Vec<u32>> = Vec::new();
-
-        for y in 0..=(target.y + target.x + (7 * 2)) {
+    fn new(depth: u32, target: Coordinate, rules: Rules) -> Scanner {
+        let depth = u64::from(depth);
+        let modulo = rules.erosion_modulo;
+        // Room for detours beyond the target, as far as the time of two
+        // switches would take moving there
+        let margin = (2 * rules.switch_minutes)
+            .checked_div(rules.move_minutes)
+            .unwrap_or(2 * rules.switch_minutes)
+            .max(1) as usize;
+        let mut erosion_levels: Vec<Vec<u64>> = Vec::new();
+
+        for y in 0..=(target.y + target.x + margin) {
             let mut row = Vec::new();
-            for x in 0..=(target.x + target.y + (7 * 2)) {
-                let geologic_index = if x == 0 && y == 0 {
-                    0
-                } else if x == target.x && y == target.y {
+            for x in 0..=(target.x + target.y + margin) {
+                let geologic_index = if (x == 0 && y == 0) || (x == target.x && y == target.y) {
                     0
                 } else if y == 0 {
-                    x as u32 * 16807
+                    x as u64 % modulo * rules.x_multiplier % modulo
                 } else if x == 0 {
-                    y as u32 * 48271
+                    y as u64 % modulo * rules.y_multiplier % modulo
                 } else {
-                    row[x - 1] * erosion_levels[y - 1][x]
+                    row[x - 1] * erosion_levels[y - 1][x] % modulo
                 };
 
-                let erosion_level = (geologic_index + depth) % 20183;
+                let erosion_level = (geologic_index + depth) % modulo;
 
                 row.push(erosion_level);
             }
@@ -88,13 +212,13 @@ impl Scanner {
         }
 
         Scanner {
-            depth,
             target,
+            rules,
             erosion_levels,
         }
     }
 
-    fn risk_level(&self) -> u32 {
+    fn risk_level(&self) -> u64 {
         let mut risk_level = 0;
 
         for y in 0..=self.target.y {
@@ -122,87 +246,259 @@ impl Scanner {
             regions.push(row);
         }
 
-        Cave { regions }
+        Cave {
+            regions,
+            rules: self.rules.clone(),
+        }
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum Step {
+    Move(Coordinate),
+    Switch(Equipment),
+}
+
+#[derive(Debug)]
+struct Route {
+    minutes: u32,
+    start: Equipment,
+    steps: Vec<Step>,
+}
+
+impl Route {
+    // Describe the route as in the puzzle example, folding consecutive
+    // moves in the same direction into a single line such as "Right 3"
+    #[allow(dead_code)]
+    fn describe(&self) -> Vec<String> {
+        let mut lines: Vec<(String, usize)> = Vec::new();
+        let mut region = Coordinate { x: 0, y: 0 };
+        let mut equipment = self.start;
+
+        for step in self.steps.iter() {
+            match step {
+                Step::Move(adjacent) => {
+                    let direction = if adjacent.x > region.x {
+                        "Right"
+                    } else if adjacent.x < region.x {
+                        "Left"
+                    } else if adjacent.y > region.y {
+                        "Down"
+                    } else {
+                        "Up"
+                    };
+                    match lines.last_mut() {
+                        Some((line, count)) if line == direction => *count += 1,
+                        _ => lines.push((direction.to_string(), 1)),
+                    }
+                    region = *adjacent;
+                }
+                Step::Switch(new_equipment) => {
+                    lines.push((
+                        format!(
+                            "Switch from using {} to {}",
+                            equipment.description(),
+                            new_equipment.description()
+                        ),
+                        0,
+                    ));
+                    equipment = *new_equipment;
+                }
+            }
+        }
+
+        lines
+            .into_iter()
+            .map(|(line, count)| {
+                if count > 1 {
+                    format!("{} {}", line, count)
+                } else {
+                    line
+                }
+            })
+            .collect()
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone)]
+enum Search {
+    Dijkstra,
+    AStar,
+}
+
+type State = (Coordinate, Equipment);
+
+// Estimated total duration, duration so far, state and predecessor state
+type Candidate = (u32, u32, State, Option<State>);
+
 #[derive(Debug)]
 struct Cave {
     regions: Vec<Vec<RegionType>>,
+    rules: Rules,
 }
 
 impl Cave {
+    // None beyond the edge of the scanned cave
+    fn region_type(&self, region: &Coordinate) -> Option<RegionType> {
+        self.regions
+            .get(region.y)
+            .and_then(|row| row.get(region.x))
+            .copied()
+    }
+
+    fn symbol(&self, region: &Coordinate, target: &Coordinate) -> char {
+        if region.x == 0 && region.y == 0 {
+            'M'
+        } else if region == target {
+            'T'
+        } else {
+            match self.regions[region.y][region.x] {
+                RegionType::Rocky => '.',
+                RegionType::Wet => '=',
+                RegionType::Narrow => '|',
+            }
+        }
+    }
+
     #[allow(dead_code)]
     fn display(&self, target: &Coordinate) {
         for y in 0..self.regions.len() {
             for x in 0..self.regions[0].len() {
-                if x == 0 && y == 0 {
-                    print!("M");
-                    continue;
-                }
-                if x == target.x && y == target.y {
-                    print!("T");
-                    continue;
+                print!("{}", self.symbol(&Coordinate { x, y }, target));
+            }
+            println!();
+        }
+    }
+
+    // Overlay the route on the map, marking each region visited with the
+    // equipment in use when leaving it: t for the torch, c for the
+    // climbing gear and n for neither
+    #[allow(dead_code)]
+    fn display_route(&self, target: &Coordinate, route: &Route) {
+        let mut visits: HashMap<Coordinate, Equipment> = HashMap::new();
+        let mut region = Coordinate { x: 0, y: 0 };
+        let mut equipment = route.start;
+
+        visits.insert(region, equipment);
+        for step in route.steps.iter() {
+            match step {
+                Step::Move(adjacent) => region = *adjacent,
+                Step::Switch(new_equipment) => equipment = *new_equipment,
+            }
+            visits.insert(region, equipment);
+        }
+
+        for y in 0..self.regions.len() {
+            for x in 0..self.regions[0].len() {
+                let region = Coordinate { x, y };
+                match visits.get(&region) {
+                    Some(equipment) => print!("{}", equipment.symbol()),
+                    None => print!("{}", self.symbol(&region, target)),
                 }
-                print!(
-                    "{}",
-                    match self.regions[y][x] {
-                        RegionType::Rocky => '.',
-                        RegionType::Wet => '=',
-                        RegionType::Narrow => '|',
-                    }
-                );
             }
             println!();
         }
     }
 
-    fn shortest_path(&self, target: &Coordinate) -> u32 {
-        use self::Equipment::*;
-        use self::RegionType::*;
+    fn shortest_path(&self, target: &Coordinate, search: Search) -> Option<Route> {
+        let rules = &self.rules;
+
+        // The heuristic never overestimates: every remaining move costs at
+        // least one move, and arriving with the wrong equipment costs at
+        // least one switch
+        let heuristic = |region: &Coordinate, equipment: Equipment| -> u32 {
+            match search {
+                Search::Dijkstra => 0,
+                Search::AStar => {
+                    let distance = (region.x as i64 - target.x as i64).abs()
+                        + (region.y as i64 - target.y as i64).abs();
+                    distance as u32 * rules.move_minutes
+                        + if equipment == rules.target_equipment {
+                            0
+                        } else {
+                            rules.switch_minutes
+                        }
+                }
+            }
+        };
+
+        let start = (Coordinate { x: 0, y: 0 }, rules.start_equipment);
+        if !rules.allows(self.regions[0][0], start.1) {
+            return None;
+        }
 
-        let mut smallest_durations: HashMap<(Coordinate, Equipment), u32> = HashMap::new();
-        let mut neighbors: BinaryHeap<Reverse<(u32, Coordinate, Equipment)>> = BinaryHeap::new();
-        neighbors.push(Reverse((0, Coordinate { x: 0, y: 0 }, Torch)));
+        let mut smallest_durations: HashMap<State, u32> = HashMap::new();
+        let mut predecessors: HashMap<State, Option<State>> = HashMap::new();
+        let mut neighbors: BinaryHeap<Reverse<Candidate>> = BinaryHeap::new();
+        neighbors.push(Reverse((heuristic(&start.0, start.1), 0, start, None)));
 
-        while let Some(Reverse((duration, region, equipment))) = neighbors.pop() {
-            if let Some(smallest_duration) = smallest_durations.get(&(region, equipment)) {
+        while let Some(Reverse((_, duration, state, predecessor))) = neighbors.pop() {
+            if let Some(smallest_duration) = smallest_durations.get(&state) {
                 if *smallest_duration <= duration {
                     continue;
                 }
             }
-            smallest_durations.insert((region, equipment), duration);
+            smallest_durations.insert(state, duration);
+            predecessors.insert(state, predecessor);
+
+            let (region, equipment) = state;
 
-            if region == *target && equipment == Torch {
-                return duration;
+            if region == *target && equipment == rules.target_equipment {
+                return Some(Route {
+                    minutes: duration,
+                    start: rules.start_equipment,
+                    steps: Cave::steps(&predecessors, state),
+                });
             }
 
             for adjacent in region.adjacents() {
-                if match (self.regions[adjacent.y][adjacent.x], equipment) {
-                    (Rocky, Torch) => true,
-                    (Rocky, ClimbingGear) => true,
-                    (Wet, ClimbingGear) => true,
-                    (Wet, Neither) => true,
-                    (Narrow, Torch) => true,
-                    (Narrow, Neither) => true,
-                    _ => false,
-                } {
-                    neighbors.push(Reverse((duration + 1, adjacent, equipment)));
+                let region_type = match self.region_type(&adjacent) {
+                    Some(region_type) => region_type,
+                    None => continue,
+                };
+                if rules.allows(region_type, equipment) {
+                    let duration = duration + rules.move_minutes;
+                    neighbors.push(Reverse((
+                        duration + heuristic(&adjacent, equipment),
+                        duration,
+                        (adjacent, equipment),
+                        Some(state),
+                    )));
+                }
+            }
+
+            for other in rules.allowed(self.regions[region.y][region.x]) {
+                if *other != equipment {
+                    let duration = duration + rules.switch_minutes;
+                    neighbors.push(Reverse((
+                        duration + heuristic(&region, *other),
+                        duration,
+                        (region, *other),
+                        Some(state),
+                    )));
                 }
             }
+        }
+
+        None
+    }
+
+    fn steps(predecessors: &HashMap<State, Option<State>>, last: State) -> Vec<Step> {
+        let mut steps = Vec::new();
+        let mut state = last;
 
-            match (self.regions[region.y][region.x], equipment) {
-                (Rocky, Torch) => neighbors.push(Reverse((duration + 7, region, ClimbingGear))),
-                (Rocky, ClimbingGear) => neighbors.push(Reverse((duration + 7, region, Torch))),
-                (Wet, ClimbingGear) => neighbors.push(Reverse((duration + 7, region, Neither))),
-                (Wet, Neither) => neighbors.push(Reverse((duration + 7, region, ClimbingGear))),
-                (Narrow, Torch) => neighbors.push(Reverse((duration + 7, region, Neither))),
-                (Narrow, Neither) => neighbors.push(Reverse((duration + 7, region, Torch))),
-                _ => (),
+        while let Some(Some(predecessor)) = predecessors.get(&state) {
+            if predecessor.0 == state.0 {
+                steps.push(Step::Switch(state.1));
+            } else {
+                steps.push(Step::Move(state.0));
             }
+            state = *predecessor;
         }
+        steps.reverse();
 
-        u32::max_value()
+        steps
     }
 }
 
@@ -215,11 +511,68 @@ fn number<'a>() -> Parser<'a, u8, usize> {
     number
         .collect()
         .convert(str::from_utf8)
-        .convert(|s| usize::from_str_radix(s, 10))
+        .convert(|s| s.parse::<usize>())
+}
+
+fn value<'a>(name: &'static [u8]) -> Parser<'a, u8, usize> {
+    seq(name) * sym(b':') * space() * number()
+}
+
+fn equipment<'a>() -> Parser<'a, u8, Equipment> {
+    seq(b"torch").map(|_| Equipment::Torch)
+        | seq(b"climbing gear").map(|_| Equipment::ClimbingGear)
+        | seq(b"neither").map(|_| Equipment::Neither)
+}
+
+fn equipment_list<'a>() -> Parser<'a, u8, Vec<Equipment>> {
+    (seq(b", ").opt() * equipment()).repeat(0..)
+}
+
+fn allowed<'a>() -> Parser<'a, u8, Setting> {
+    let region_type = seq(b"rocky").map(|_| RegionType::Rocky)
+        | seq(b"wet").map(|_| RegionType::Wet)
+        | seq(b"narrow").map(|_| RegionType::Narrow);
+
+    (region_type - sym(b':') - one_of(b" \t").repeat(0..) + equipment_list())
+        .map(|(region_type, equipment)| Setting::Allowed(region_type, equipment))
+}
+
+fn setting<'a>() -> Parser<'a, u8, Setting> {
+    value(b"x multiplier").map(Setting::XMultiplier)
+        | value(b"y multiplier").map(Setting::YMultiplier)
+        | value(b"erosion modulo").map(Setting::ErosionModulo)
+        | value(b"move minutes").map(Setting::MoveMinutes)
+        | value(b"switch minutes").map(Setting::SwitchMinutes)
+        | (seq(b"start equipment:") * space() * equipment()).map(Setting::StartEquipment)
+        | (seq(b"target equipment:") * space() * equipment()).map(Setting::TargetEquipment)
+        | allowed()
+}
+
+// A rules file holds one setting per line, in any order; settings which
+// are left out keep the values from the puzzle description. Multipliers
+// and the modulo must fit in 32 bits, the modulo can't be zero and minutes
+// are at most MAX_MINUTES. For example
+//
+//   x multiplier: 16807
+//   y multiplier: 48271
+//   erosion modulo: 20183
+//   move minutes: 1
+//   switch minutes: 7
+//   rocky: torch, climbing gear
+//   wet: climbing gear, neither
+//   narrow: torch, neither
+//   start equipment: torch
+//   target equipment: torch
+fn rules<'a>() -> Parser<'a, u8, Rules> {
+    ((space() * setting()).repeat(0..) - space() - end()).convert(|settings| {
+        settings
+            .into_iter()
+            .try_fold(Rules::default(), Rules::apply)
+    })
 }
 
 fn depth<'a>() -> Parser<'a, u8, u32> {
-    space() * seq(b"depth:") * space() * number().map(|x| x as u32)
+    space() * seq(b"depth:") * space() * number().convert(u32::try_from)
 }
 
 fn target<'a>() -> Parser<'a, u8, Coordinate> {
@@ -229,25 +582,184 @@ fn target<'a>() -> Parser<'a, u8, Coordinate> {
         * (number() + (sym(b',') * number())).map(|(x, y)| Coordinate { x, y })
 }
 
-fn scanner<'a>() -> Parser<'a, u8, Scanner> {
-    (depth() + target()).map(|(depth, target)| Scanner::new(depth, target))
+fn scan<'a>() -> Parser<'a, u8, (u32, Coordinate)> {
+    depth() + target()
 }
 
 fn main() -> Result<(), Error> {
     let mut input = String::new();
     stdin().read_to_string(&mut input).unwrap();
 
-    let scanner = scanner().parse(input.as_bytes())?;
+    // An optional rules file overrides the cave parameters of the puzzle
+    let rules_input = match env::args().nth(1) {
+        Some(path) => fs::read_to_string(path).expect("Unable to read rules file"),
+        None => String::new(),
+    };
+    let rules = rules().parse(rules_input.as_bytes())?;
+
+    let (depth, target) = scan().parse(input.as_bytes())?;
+    let scanner = Scanner::new(depth, target, rules);
 
     let cave = scanner.cave();
     //cave.display(&scanner.target);
 
     println!("Part 1: the total risk level is {}", scanner.risk_level());
 
-    println!(
-        "Part 2: the fewest minutes needed to reach the target is {}",
-        cave.shortest_path(&scanner.target)
-    );
+    match cave.shortest_path(&scanner.target, Search::AStar) {
+        Some(route) => {
+            //cave.display_route(&scanner.target, &route);
+            //for line in route.describe() {
+            //    println!("{}", line);
+            //}
+            println!(
+                "Part 2: the fewest minutes needed to reach the target is {}",
+                route.minutes
+            );
+        }
+        None => println!("Part 2: the target cannot be reached with these rules"),
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> (Scanner, Cave) {
+        let scanner = Scanner::new(510, Coordinate { x: 10, y: 10 }, Rules::default());
+        let cave = scanner.cave();
+        (scanner, cave)
+    }
+
+    #[test]
+    fn test_risk_level() {
+        let (scanner, _) = example();
+        assert_eq!(scanner.risk_level(), 114);
+    }
+
+    #[test]
+    fn test_shortest_path_dijkstra() {
+        let (scanner, cave) = example();
+        let route = cave
+            .shortest_path(&scanner.target, Search::Dijkstra)
+            .unwrap();
+        assert_eq!(route.minutes, 45);
+    }
+
+    #[test]
+    fn test_shortest_path_astar() {
+        let (scanner, cave) = example();
+        let route = cave.shortest_path(&scanner.target, Search::AStar).unwrap();
+        assert_eq!(route.minutes, 45);
+    }
+
+    #[test]
+    fn test_route_is_valid() {
+        let (scanner, cave) = example();
+        let route = cave.shortest_path(&scanner.target, Search::AStar).unwrap();
+
+        let rules = &cave.rules;
+        let mut region = Coordinate { x: 0, y: 0 };
+        let mut equipment = rules.start_equipment;
+        let mut minutes = 0;
+        for step in route.steps.iter() {
+            match step {
+                Step::Move(adjacent) => {
+                    assert!(region.adjacents().contains(adjacent));
+                    region = *adjacent;
+                    minutes += rules.move_minutes;
+                }
+                Step::Switch(new_equipment) => {
+                    assert_ne!(equipment, *new_equipment);
+                    equipment = *new_equipment;
+                    minutes += rules.switch_minutes;
+                }
+            }
+            assert!(rules.allows(cave.regions[region.y][region.x], equipment));
+        }
+
+        assert_eq!(region, scanner.target);
+        assert_eq!(equipment, rules.target_equipment);
+        assert_eq!(minutes, route.minutes);
+
+        let switches = route
+            .steps
+            .iter()
+            .filter(|step| matches!(step, Step::Switch(_)))
+            .count();
+        let described_switches = route
+            .describe()
+            .iter()
+            .filter(|line| line.starts_with("Switch"))
+            .count();
+        assert_eq!(switches, described_switches);
+    }
+
+    #[test]
+    fn test_default_rules_file() {
+        let input = "x multiplier: 16807
+y multiplier: 48271
+erosion modulo: 20183
+move minutes: 1
+switch minutes: 7
+rocky: torch, climbing gear
+wet: climbing gear, neither
+narrow: torch, neither
+start equipment: torch
+target equipment: torch
+";
+        let rules = rules().parse(input.as_bytes()).unwrap();
+        let scanner = Scanner::new(510, Coordinate { x: 10, y: 10 }, rules);
+        let cave = scanner.cave();
+        let route = cave.shortest_path(&scanner.target, Search::AStar).unwrap();
+        assert_eq!(scanner.risk_level(), 114);
+        assert_eq!(route.minutes, 45);
+    }
+
+    #[test]
+    fn test_variant_rules() {
+        let input = "switch minutes: 0\nwet: climbing gear, neither, torch\n";
+        let rules = rules().parse(input.as_bytes()).unwrap();
+        assert_eq!(rules.x_multiplier, 16807);
+        let scanner = Scanner::new(510, Coordinate { x: 10, y: 10 }, rules);
+        let cave = scanner.cave();
+        let route = cave.shortest_path(&scanner.target, Search::AStar).unwrap();
+        assert_eq!(route.minutes, 20);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        for input in [
+            "erosion modulo: 0\n",
+            "erosion modulo: 4294967296\n",
+            "x multiplier: 4294967296\n",
+            "switch minutes: 1001\n",
+            "move minutes: 99999999999\n",
+        ]
+        .iter()
+        {
+            assert!(rules().parse(input.as_bytes()).is_err(), "{}", input);
+        }
+
+        let rules = rules()
+            .parse(b"erosion modulo: 4294967295\nswitch minutes: 1000\n")
+            .unwrap();
+        assert_eq!(rules.erosion_modulo, 4294967295);
+        assert_eq!(rules.switch_minutes, 1000);
+        assert!(depth().parse(b"depth: 4294967296").is_err());
+    }
+
+    #[test]
+    fn test_unreachable_target() {
+        // The target is rocky, where only the torch is allowed
+        let input = "target equipment: neither\nrocky: torch\n";
+        let rules = rules().parse(input.as_bytes()).unwrap();
+        let scanner = Scanner::new(510, Coordinate { x: 10, y: 10 }, rules);
+        let cave = scanner.cave();
+        assert!(cave.shortest_path(&scanner.target, Search::AStar).is_none());
+        assert!(cave
+            .shortest_path(&scanner.target, Search::Dijkstra)
+            .is_none());
+    }
+}
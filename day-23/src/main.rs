@@ -13,6 +13,13 @@
 // for each subcube, and put the subcubes into the queue. Then rinse and 
 // repeat. The first cube of size 1 that we pull is the solution.
 //
+// Popping the smallest cube rather than the closest one finds the highest
+// nanobot count quickly, but not necessarily the closest point with that
+// count, so a second search over only the cubes which can reach that count
+// pops them in order of distance instead. The starting cube must enclose
+// the range of every nanobot as well as the origin, whichever side of the
+// origin the nanobots lie on.
+//
 
 use pom::parser::*;
 use pom::Error;
@@ -21,16 +28,16 @@ use std::collections::BinaryHeap;
 use std::io::{stdin, Read};
 use std::str;
 
-#[derive(Debug, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
 struct Cube {
-    extent: i32,
-    base_x: i32,
-    base_y: i32,
-    base_z: i32,
+    extent: i64,
+    base_x: i64,
+    base_y: i64,
+    base_z: i64,
 }
 
 impl Cube {
-    fn new(extent: i32) -> Cube {
+    fn new(extent: i64) -> Cube {
         Cube {
             extent,
             base_x: -(extent / 2),
@@ -64,22 +71,40 @@ impl Cube {
 
         cubes
     }
+
+    // The manhattan distance from the origin to the nearest point in the cube
+    fn distance(&self) -> i64 {
+        let axis_distance = |base: i64| {
+            if base > 0 {
+                base
+            } else if base + self.extent - 1 < 0 {
+                -(base + self.extent - 1)
+            } else {
+                0
+            }
+        };
+
+        axis_distance(self.base_x) + axis_distance(self.base_y) + axis_distance(self.base_z)
+    }
 }
 
 #[derive(Debug)]
 struct Nanobot {
-    x: i32,
-    y: i32,
-    z: i32,
-    r: i32,
+    x: i64,
+    y: i64,
+    z: i64,
+    r: i64,
 }
 
 impl Nanobot {
     fn in_range_of_nanobot(&self, nanobot: &Nanobot) -> bool {
-        let distance =
-            (nanobot.x - self.x).abs() + (nanobot.y - self.y).abs() + (nanobot.z - self.z).abs();
+        nanobot.in_range_of_point(self.x, self.y, self.z)
+    }
+
+    fn in_range_of_point(&self, x: i64, y: i64, z: i64) -> bool {
+        let distance = (x - self.x).abs() + (y - self.y).abs() + (z - self.z).abs();
 
-        distance <= nanobot.r
+        distance <= self.r
     }
 
     fn in_range_of_cube(&self, cube: &Cube) -> bool {
@@ -111,12 +136,315 @@ impl Nanobot {
     }
 }
 
+fn diagonals(x: i64, y: i64, z: i64) -> [i64; 4] {
+    [x + y + z, x + y - z, x - y + z, -x + y + z]
+}
+
+// Measured along the four diagonals, the range of a nanobot is a box, so
+// the points in range of several nanobots form a box as well. Only boxes
+// with a+b+c = d, where a, b and c are the last three diagonals and d the
+// first, and with a, b and c of equal parity, hold integer points.
+#[derive(Debug)]
+struct Region {
+    nanobots: Vec<usize>,
+    bounds: [(i64, i64); 4],
+}
+
+impl Region {
+    fn new(nanobots: &[Nanobot], indices: Vec<usize>) -> Option<Region> {
+        let mut bounds = [(i64::MIN, i64::MAX); 4];
+
+        for &index in indices.iter() {
+            let nanobot = &nanobots[index];
+            let centre = diagonals(nanobot.x, nanobot.y, nanobot.z);
+            for (bound, centre) in bounds.iter_mut().zip(centre.iter()) {
+                bound.0 = bound.0.max(centre - nanobot.r);
+                bound.1 = bound.1.min(centre + nanobot.r);
+            }
+        }
+
+        let region = Region {
+            nanobots: indices,
+            bounds,
+        };
+        if region.is_empty() {
+            None
+        } else {
+            Some(region)
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        let [sum, a, b, c] = self.bounds;
+
+        (0..2).all(|parity| {
+            // Narrow each of a, b and c to the values of the given parity
+            let narrowed: Vec<(i64, i64)> = [a, b, c]
+                .iter()
+                .map(|&(low, high)| {
+                    (
+                        low + (low - parity).rem_euclid(2),
+                        high - (high - parity).rem_euclid(2),
+                    )
+                })
+                .collect();
+            if narrowed.iter().any(|(low, high)| low > high) {
+                return true;
+            }
+
+            // a+b+c then takes every value of that parity in this range
+            let low = narrowed.iter().map(|(low, _)| low).sum::<i64>().max(sum.0);
+            let high = narrowed
+                .iter()
+                .map(|(_, high)| high)
+                .sum::<i64>()
+                .min(sum.1);
+            let low = low + (low - parity).rem_euclid(2);
+            low > high
+        })
+    }
+
+    fn is_point(&self) -> bool {
+        self.bounds.iter().all(|(low, high)| low == high)
+    }
+
+    fn contains(&self, x: i64, y: i64, z: i64) -> bool {
+        diagonals(x, y, z)
+            .iter()
+            .zip(self.bounds.iter())
+            .all(|(diagonal, (low, high))| low <= diagonal && diagonal <= high)
+    }
+
+    // The manhattan distance of a point from the origin is the largest
+    // absolute value of its diagonals, so this is a lower bound
+    fn distance(&self) -> i64 {
+        self.bounds
+            .iter()
+            .map(|&(low, high)| {
+                if low > 0 {
+                    low
+                } else if high < 0 {
+                    -high
+                } else {
+                    0
+                }
+            })
+            .max()
+            .unwrap()
+    }
+}
+
+// A bounding volume hierarchy over the nanobots. Each node keeps the
+// bounds of its nanobots' positions, and of their ranges measured along
+// the four diagonals, so that whole subtrees can be skipped by queries.
+#[derive(Debug)]
+struct Node {
+    start: usize,
+    end: usize,
+    children: Option<(usize, usize)>,
+    positions: [(i64, i64); 3],
+    ranges: [(i64, i64); 4],
+}
+
+#[derive(Debug)]
+struct NanobotIndex {
+    order: Vec<usize>,
+    nodes: Vec<Node>,
+}
+
+impl NanobotIndex {
+    const LEAF_SIZE: usize = 8;
+
+    fn new(nanobots: &[Nanobot]) -> NanobotIndex {
+        let mut index = NanobotIndex {
+            order: (0..nanobots.len()).collect(),
+            nodes: Vec::new(),
+        };
+        if !nanobots.is_empty() {
+            index.build(nanobots, 0, nanobots.len());
+        }
+
+        index
+    }
+
+    fn build(&mut self, nanobots: &[Nanobot], start: usize, end: usize) -> usize {
+        let mut positions = [(i64::MAX, i64::MIN); 3];
+        let mut ranges = [(i64::MAX, i64::MIN); 4];
+        for &j in self.order[start..end].iter() {
+            let nanobot = &nanobots[j];
+            for (bound, position) in positions
+                .iter_mut()
+                .zip([nanobot.x, nanobot.y, nanobot.z].iter())
+            {
+                bound.0 = bound.0.min(*position);
+                bound.1 = bound.1.max(*position);
+            }
+            for (bound, centre) in ranges
+                .iter_mut()
+                .zip(diagonals(nanobot.x, nanobot.y, nanobot.z).iter())
+            {
+                bound.0 = bound.0.min(centre - nanobot.r);
+                bound.1 = bound.1.max(centre + nanobot.r);
+            }
+        }
+
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            start,
+            end,
+            children: None,
+            positions,
+            ranges,
+        });
+
+        if end - start > NanobotIndex::LEAF_SIZE {
+            // Split at the median position along the widest axis
+            let axis = (0..3)
+                .max_by_key(|&axis| positions[axis].1 - positions[axis].0)
+                .unwrap();
+            let coordinate = |j: &usize| match axis {
+                0 => nanobots[*j].x,
+                1 => nanobots[*j].y,
+                _ => nanobots[*j].z,
+            };
+            let middle = (start + end) / 2;
+            self.order[start..end].select_nth_unstable_by_key(middle - start, coordinate);
+
+            let left = self.build(nanobots, start, middle);
+            let right = self.build(nanobots, middle, end);
+            self.nodes[node].children = Some((left, right));
+        }
+
+        node
+    }
+
+    // Visit every nanobot in a subtree which the node filter cannot rule
+    // out, leaving the exact test to the visitor
+    fn search<F, G>(&self, node_filter: F, mut visit: G)
+    where
+        F: Fn(&Node) -> bool,
+        G: FnMut(usize),
+    {
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if !node_filter(node) {
+                continue;
+            }
+            match node.children {
+                Some((left, right)) => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+                None => self.order[node.start..node.end]
+                    .iter()
+                    .for_each(|&j| visit(j)),
+            }
+        }
+    }
+
+    // The nanobots whose range covers the point
+    fn covering_point(&self, nanobots: &[Nanobot], x: i64, y: i64, z: i64) -> Vec<usize> {
+        let point = diagonals(x, y, z);
+        let mut covering = Vec::new();
+
+        self.search(
+            |node| {
+                node.ranges
+                    .iter()
+                    .zip(point.iter())
+                    .all(|((low, high), diagonal)| low <= diagonal && diagonal <= high)
+            },
+            |j| {
+                if nanobots[j].in_range_of_point(x, y, z) {
+                    covering.push(j);
+                }
+            },
+        );
+
+        covering.sort_unstable();
+        covering
+    }
+
+    // The nanobots whose range intersects the cube
+    fn intersecting_cube(&self, nanobots: &[Nanobot], cube: &Cube) -> Vec<usize> {
+        // Along each diagonal the cube spans three times its extent, starting
+        // from the corner which has the smallest value on that diagonal
+        let span = cube.extent - 1;
+        let base = diagonals(cube.base_x, cube.base_y, cube.base_z);
+        let lows = [base[0], base[1] - span, base[2] - span, base[3] - span];
+        let mut intersecting = Vec::new();
+
+        self.search(
+            |node| {
+                node.ranges
+                    .iter()
+                    .zip(lows.iter())
+                    .all(|((low, high), cube_low)| {
+                        *low <= cube_low + 3 * span && *cube_low <= *high
+                    })
+            },
+            |j| {
+                if nanobots[j].in_range_of_cube(cube) {
+                    intersecting.push(j);
+                }
+            },
+        );
+
+        intersecting.sort_unstable();
+        intersecting
+    }
+
+    // The nanobots positioned within range of the given nanobot
+    fn in_range_of(&self, nanobots: &[Nanobot], nanobot: &Nanobot) -> Vec<usize> {
+        let mut in_range = Vec::new();
+
+        self.search(
+            |node| {
+                let distance: i64 = node
+                    .positions
+                    .iter()
+                    .zip([nanobot.x, nanobot.y, nanobot.z].iter())
+                    .map(|((low, high), position)| (low - position).max(position - high).max(0))
+                    .sum();
+                distance <= nanobot.r
+            },
+            |j| {
+                if nanobots[j].in_range_of_nanobot(nanobot) {
+                    in_range.push(j);
+                }
+            },
+        );
+
+        in_range.sort_unstable();
+        in_range
+    }
+}
+
+#[derive(Debug)]
+struct Solution {
+    cube: Cube,
+    nanobot_count: usize,
+    distance: i64,
+}
+
 #[derive(Debug)]
 struct Engine {
     nanobots: Vec<Nanobot>,
+    index: NanobotIndex,
 }
 
 impl Engine {
+    fn new(nanobots: Vec<Nanobot>) -> Engine {
+        let index = NanobotIndex::new(&nanobots);
+
+        Engine { nanobots, index }
+    }
+
     fn in_range_of_strongest(&self) -> usize {
         let strongest = self
             .nanobots
@@ -124,25 +452,32 @@ impl Engine {
             .max_by(|a, b| a.r.cmp(&b.r))
             .expect("One or more nanobots required");
 
-        let mut in_range_count = 0;
+        self.index.in_range_of(&self.nanobots, strongest).len()
+    }
 
-        for nanobot in self.nanobots.iter() {
-            if nanobot.in_range_of_nanobot(&strongest) {
-                in_range_count += 1;
-            }
-        }
+    fn run(&self) -> Solution {
+        let nanobot_count = self.highest_nanobot_count();
+        let cube = self.closest_cube(nanobot_count);
 
-        in_range_count
+        Solution {
+            cube,
+            nanobot_count,
+            distance: cube.distance(),
+        }
     }
 
-    fn run(&self) -> Cube {
+    // The nanobot count of a cube bounds the count of any point in it, so
+    // the first cube of extent 1 popped has the highest count. Smaller cubes
+    // are popped first so that one is reached quickly.
+    fn highest_nanobot_count(&self) -> usize {
         let mut cubes = BinaryHeap::new();
 
-        cubes.push((self.nanobots.len(), Reverse(self.generate_starting_cube())));
+        let starting_cube = self.generate_starting_cube();
+        cubes.push((self.nanobot_count(&starting_cube), Reverse(starting_cube)));
 
-        while let Some((_nanobot_count, Reverse(cube))) = cubes.pop() {
+        while let Some((nanobot_count, Reverse(cube))) = cubes.pop() {
             if cube.extent == 1 {
-                return cube;
+                return nanobot_count;
             }
             for subcube in cube.subdivide() {
                 cubes.push((self.nanobot_count(&subcube), Reverse(subcube)));
@@ -151,51 +486,108 @@ impl Engine {
         panic!("A cube with extent 1 should have been found");
     }
 
-    fn nanobot_count(&self, cube: &Cube) -> usize {
-        let mut nanobot_count = 0;
+    // With the highest count known, every cube which cannot reach it is
+    // discarded, and the rest are popped in order of a lower bound on the
+    // distance of their points with that count, smaller cubes first. The
+    // first cube of extent 1 popped is then the closest point with that
+    // count.
+    fn closest_cube(&self, nanobot_count: usize) -> Cube {
+        let mut cubes = BinaryHeap::new();
+
+        let starting_cube = self.generate_starting_cube();
+        cubes.push(Reverse((0, starting_cube)));
 
-        for nanobot in self.nanobots.iter() {
-            if nanobot.in_range_of_cube(&cube) {
-                nanobot_count += 1;
+        while let Some(Reverse((_distance, cube))) = cubes.pop() {
+            if cube.extent == 1 {
+                return cube;
+            }
+            for subcube in cube.subdivide() {
+                if let Some(distance) = self.distance_bound(&subcube, nanobot_count) {
+                    cubes.push(Reverse((distance, subcube)));
+                }
             }
         }
-
-        nanobot_count
+        panic!("A cube with extent 1 should have been found");
     }
 
-    fn generate_starting_cube(&self) -> Cube {
-        let (mut min_x, mut max_x) = (i32::max_value(), i32::min_value());
-        let (mut min_y, mut max_y) = (i32::max_value(), i32::min_value());
-        let (mut min_z, mut max_z) = (i32::max_value(), i32::min_value());
+    // A point in range of nanobot_count nanobots is at least as far from
+    // the origin as the nearest point in range of each of them, so within
+    // the cube it is no closer than the nanobot_count-th smallest of those
+    // distances. None if the cube cannot reach nanobot_count at all.
+    fn distance_bound(&self, cube: &Cube, nanobot_count: usize) -> Option<i64> {
+        let mut distances: Vec<i64> = self
+            .index
+            .intersecting_cube(&self.nanobots, cube)
+            .into_iter()
+            .map(|j| &self.nanobots[j])
+            .map(|nanobot| nanobot.x.abs() + nanobot.y.abs() + nanobot.z.abs() - nanobot.r)
+            .collect();
+        if nanobot_count == 0 {
+            return Some(cube.distance());
+        }
+        if distances.len() < nanobot_count {
+            return None;
+        }
+        distances.sort_unstable();
 
-        for nanobot in self.nanobots.iter() {
-            if nanobot.x < min_x {
-                min_x = nanobot.x;
-            }
-            if nanobot.x > max_x {
-                max_x = nanobot.x;
-            }
-            if nanobot.y < min_y {
-                min_y = nanobot.y;
-            }
-            if nanobot.y > max_y {
-                max_y = nanobot.y;
-            }
-            if nanobot.z < min_z {
-                min_z = nanobot.z;
+        Some(cube.distance().max(distances[nanobot_count - 1]))
+    }
+
+    // Find every region of points in range of nanobot_count nanobots. Since
+    // no point is in range of more nanobots than the solution, a cube which
+    // intersects exactly that many can only hold points in range of all of
+    // them, so only cubes intersecting more need to be subdivided.
+    fn tied_regions(&self, nanobot_count: usize) -> Vec<Region> {
+        let mut regions: Vec<Region> = Vec::new();
+        let mut cubes = vec![self.generate_starting_cube()];
+
+        while let Some(cube) = cubes.pop() {
+            let nanobots = self.index.intersecting_cube(&self.nanobots, &cube);
+            if nanobots.len() < nanobot_count {
+                continue;
             }
-            if nanobot.z > max_z {
-                max_z = nanobot.z;
+            if nanobots.len() == nanobot_count {
+                if !regions.iter().any(|region| region.nanobots == nanobots) {
+                    if let Some(region) = Region::new(&self.nanobots, nanobots) {
+                        regions.push(region);
+                    }
+                }
+                continue;
             }
+            cubes.append(&mut cube.subdivide());
         }
 
-        let maximum_boundary = *[min_x, max_x, min_y, max_y, min_z, max_z]
+        regions.sort_by_key(|region| region.distance());
+        regions
+    }
+
+    fn nanobot_count(&self, cube: &Cube) -> usize {
+        self.index.intersecting_cube(&self.nanobots, cube).len()
+    }
+
+    fn nanobot_count_at_point(&self, x: i64, y: i64, z: i64) -> usize {
+        self.index.covering_point(&self.nanobots, x, y, z).len()
+    }
+
+    fn generate_starting_cube(&self) -> Cube {
+        // The cube is centered on the origin, so it must reach as far as
+        // the farthest point in range of any nanobot, in either direction
+        let maximum_boundary = self
+            .nanobots
             .iter()
+            .map(|nanobot| {
+                *[nanobot.x.abs(), nanobot.y.abs(), nanobot.z.abs()]
+                    .iter()
+                    .max()
+                    .unwrap()
+                    + nanobot.r
+            })
             .max()
-            .unwrap();
+            .unwrap_or(0);
 
+        // A cube of extent e spans -e/2 to e/2 - 1 on each axis
         let mut extent = 1;
-        while extent < maximum_boundary {
+        while extent / 2 - 1 < maximum_boundary {
             extent *= 2;
         }
 
@@ -207,13 +599,13 @@ fn space<'a>() -> Parser<'a, u8, ()> {
     one_of(b" \t\r\n").repeat(0..).discard()
 }
 
-fn number<'a>() -> Parser<'a, u8, i32> {
+fn number<'a>() -> Parser<'a, u8, i64> {
     let integer = (one_of(b"123456789") - one_of(b"0123456789").repeat(0..)) | sym(b'0');
     let number = sym(b'-').opt() + integer;
     number
         .collect()
         .convert(str::from_utf8)
-        .convert(|s| i32::from_str_radix(s, 10))
+        .convert(|s| s.parse::<i64>())
 }
 
 fn nanobot<'a>() -> Parser<'a, u8, Nanobot> {
@@ -223,9 +615,7 @@ fn nanobot<'a>() -> Parser<'a, u8, Nanobot> {
 }
 
 fn engine<'a>() -> Parser<'a, u8, Engine> {
-    (space() * nanobot())
-        .repeat(1..)
-        .map(|nanobots| Engine { nanobots })
+    (space() * nanobot()).repeat(1..).map(Engine::new)
 }
 
 fn main() -> Result<(), Error> {
@@ -238,12 +628,274 @@ fn main() -> Result<(), Error> {
         engine.in_range_of_strongest()
     );
 
-    let cube = engine.run();
-    let shortest_distance = cube.base_x.abs() + cube.base_y.abs() + cube.base_z.abs();
+    // Report rather than panic if the searches disagree about the input
+    let inconsistent = |message: &str| Error::Custom {
+        message: message.to_string(),
+        position: 0,
+        inner: None,
+    };
+
+    let solution = engine.run();
+    let cube = &solution.cube;
+    if engine.nanobot_count_at_point(cube.base_x, cube.base_y, cube.base_z)
+        != solution.nanobot_count
+    {
+        return Err(inconsistent(
+            "the closest point is not in range of as many nanobots as its cube",
+        ));
+    }
     println!(
-        "Part 2: the shortest manhattan distance is {}",
-        shortest_distance
+        "Part 2: the shortest manhattan distance is {} ({},{},{} is in range of {} nanobots)",
+        solution.distance, cube.base_x, cube.base_y, cube.base_z, solution.nanobot_count
     );
 
+    let regions = engine.tied_regions(solution.nanobot_count);
+    if !regions
+        .iter()
+        .any(|region| region.contains(cube.base_x, cube.base_y, cube.base_z))
+    {
+        return Err(inconsistent(
+            "the closest point is not in any region tied for the most nanobots",
+        ));
+    }
+    if regions.len() > 1 || regions.iter().any(|region| !region.is_point()) {
+        println!(
+            "{} region(s) are tied for the most nanobots in range:",
+            regions.len()
+        );
+        for region in regions.iter() {
+            let [(a, b), (c, d), (e, f), (g, h)] = region.bounds;
+            println!(
+                "  {}<=x+y+z<={}, {}<=x+y-z<={}, {}<=x-y+z<={}, {}<=-x+y+z<={}",
+                a, b, c, d, e, f, g, h
+            );
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_part1() -> Engine {
+        let input = "pos=<0,0,0>, r=4
+pos=<1,0,0>, r=1
+pos=<4,0,0>, r=3
+pos=<0,2,0>, r=1
+pos=<0,5,0>, r=3
+pos=<0,0,3>, r=1
+pos=<1,1,1>, r=1
+pos=<1,1,2>, r=1
+pos=<1,3,1>, r=1";
+        engine().parse(input.as_bytes()).unwrap()
+    }
+
+    fn example_part2() -> Engine {
+        let input = "pos=<10,12,12>, r=2
+pos=<12,14,12>, r=2
+pos=<16,12,12>, r=4
+pos=<14,14,14>, r=6
+pos=<50,50,50>, r=200
+pos=<10,10,10>, r=5";
+        engine().parse(input.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_in_range_of_strongest() {
+        assert_eq!(example_part1().in_range_of_strongest(), 7);
+    }
+
+    #[test]
+    fn test_run() {
+        let engine = example_part2();
+        let solution = engine.run();
+        assert_eq!((solution.cube.base_x, solution.cube.base_y), (12, 12));
+        assert_eq!(solution.cube.base_z, 12);
+        assert_eq!(solution.nanobot_count, 5);
+        assert_eq!(solution.distance, 36);
+        let regions = engine.tied_regions(5);
+        assert_eq!(regions.len(), 1);
+        assert!(regions[0].contains(12, 12, 12));
+    }
+
+    #[test]
+    fn test_run_negative() {
+        let nanobots = example_part2()
+            .nanobots
+            .into_iter()
+            .map(|nanobot| Nanobot {
+                x: -nanobot.x,
+                y: -nanobot.y,
+                z: -nanobot.z,
+                r: nanobot.r,
+            })
+            .collect();
+        let engine = Engine::new(nanobots);
+        let solution = engine.run();
+        assert_eq!((solution.cube.base_x, solution.cube.base_y), (-12, -12));
+        assert_eq!(solution.cube.base_z, -12);
+        assert_eq!(solution.nanobot_count, 5);
+        assert_eq!(solution.distance, 36);
+    }
+
+    #[test]
+    fn test_run_far_from_origin() {
+        let input = "pos=<-1000,-2000,-3000>, r=0";
+        let engine = engine().parse(input.as_bytes()).unwrap();
+        let solution = engine.run();
+        assert_eq!(solution.nanobot_count, 1);
+        assert_eq!(solution.distance, 6000);
+    }
+
+    #[test]
+    fn test_tied_regions() {
+        let input = "pos=<-20,0,0>, r=1
+pos=<20,0,0>, r=1
+pos=<0,30,0>, r=2
+pos=<0,31,0>, r=2";
+        let engine = engine().parse(input.as_bytes()).unwrap();
+        let solution = engine.run();
+        assert_eq!(solution.nanobot_count, 2);
+        assert_eq!(solution.distance, 29);
+        let regions = engine.tied_regions(2);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].nanobots, vec![2, 3]);
+        assert_eq!(regions[0].distance(), 29);
+        assert!(regions[0].contains(0, 29, 0));
+        assert!(regions[0].contains(1, 30, 0));
+        assert!(!regions[0].contains(0, 28, 0));
+
+        let regions = engine.tied_regions(1);
+        assert_eq!(regions.len(), 4);
+        assert_eq!(regions[0].distance(), 19);
+    }
+
+    // A simple generator keeps the synthetic swarms deterministic
+    struct Generator {
+        seed: u64,
+    }
+
+    impl Generator {
+        fn next(&mut self, low: i64, high: i64) -> i64 {
+            self.seed = self
+                .seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            low + ((self.seed >> 33) % (high - low + 1) as u64) as i64
+        }
+
+        fn swarm(&mut self, count: usize, coordinate: i64, radius: (i64, i64)) -> Vec<Nanobot> {
+            (0..count)
+                .map(|_| Nanobot {
+                    x: self.next(-coordinate, coordinate),
+                    y: self.next(-coordinate, coordinate),
+                    z: self.next(-coordinate, coordinate),
+                    r: self.next(radius.0, radius.1),
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_region_is_empty() {
+        // Compare against a brute force search on small nanobots
+        let mut generator = Generator { seed: 2018 };
+
+        for _ in 0..500 {
+            let nanobots = generator.swarm(3, 3, (0, 3));
+            let mut expected = false;
+            for x in -6..=6 {
+                for y in -6..=6 {
+                    for z in -6..=6 {
+                        if nanobots.iter().all(|n| n.in_range_of_point(x, y, z)) {
+                            expected = true;
+                        }
+                    }
+                }
+            }
+            assert_eq!(Region::new(&nanobots, vec![0, 1, 2]).is_some(), expected);
+        }
+    }
+
+    #[test]
+    fn test_index() {
+        let mut generator = Generator { seed: 23 };
+        let nanobots = generator.swarm(2000, 1000, (50, 400));
+        let index = NanobotIndex::new(&nanobots);
+
+        for _ in 0..100 {
+            let (x, y, z) = (
+                generator.next(-1200, 1200),
+                generator.next(-1200, 1200),
+                generator.next(-1200, 1200),
+            );
+            let covering: Vec<usize> = (0..nanobots.len())
+                .filter(|&j| nanobots[j].in_range_of_point(x, y, z))
+                .collect();
+            assert_eq!(index.covering_point(&nanobots, x, y, z), covering);
+
+            let cube = Cube {
+                extent: 1 << generator.next(0, 9),
+                base_x: x,
+                base_y: y,
+                base_z: z,
+            };
+            let intersecting: Vec<usize> = (0..nanobots.len())
+                .filter(|&j| nanobots[j].in_range_of_cube(&cube))
+                .collect();
+            assert_eq!(index.intersecting_cube(&nanobots, &cube), intersecting);
+
+            let nanobot = &nanobots[generator.next(0, 1999) as usize];
+            let in_range: Vec<usize> = (0..nanobots.len())
+                .filter(|&j| nanobots[j].in_range_of_nanobot(nanobot))
+                .collect();
+            assert_eq!(index.in_range_of(&nanobots, nanobot), in_range);
+        }
+    }
+
+    // Times 1000 point queries over 100000 nanobots, indexed and linear
+    #[test]
+    #[ignore]
+    fn bench_index() {
+        use std::time::Instant;
+
+        let mut generator = Generator { seed: 100_000 };
+        let nanobots = generator.swarm(100_000, 100_000_000, (1_000_000, 20_000_000));
+
+        let start = Instant::now();
+        let index = NanobotIndex::new(&nanobots);
+        println!("index: built over 100000 nanobots in {:?}", start.elapsed());
+
+        let points: Vec<(i64, i64, i64)> = (0..1000)
+            .map(|_| {
+                (
+                    generator.next(-100_000_000, 100_000_000),
+                    generator.next(-100_000_000, 100_000_000),
+                    generator.next(-100_000_000, 100_000_000),
+                )
+            })
+            .collect();
+
+        let start = Instant::now();
+        let linear: usize = points
+            .iter()
+            .map(|&(x, y, z)| {
+                nanobots
+                    .iter()
+                    .filter(|nanobot| nanobot.in_range_of_point(x, y, z))
+                    .count()
+            })
+            .sum();
+        println!("linear: 1000 point queries in {:?}", start.elapsed());
+
+        let start = Instant::now();
+        let indexed: usize = points
+            .iter()
+            .map(|&(x, y, z)| index.covering_point(&nanobots, x, y, z).len())
+            .sum();
+        println!("index: 1000 point queries in {:?}", start.elapsed());
+        assert_eq!(linear, indexed);
+    }
+}
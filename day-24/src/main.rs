@@ -3,19 +3,16 @@ use pom::Error;
 use std::cmp::{Ordering, Reverse};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
 use std::io::{stdin, Read};
 use std::str;
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-enum AttackType {
-    Bludgeoning,
-    Cold,
-    Fire,
-    Radiation,
-    Slashing,
-}
+// Attack types are whatever names the input uses, such as "fire"
+type AttackType = String;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Group {
     units: u32,
     hit_points: u32,
@@ -27,14 +24,22 @@ struct Group {
 }
 
 impl Group {
-    fn effective_power(&self) -> u32 {
-        self.units * self.attack_damage
+    fn effective_power(&self) -> u64 {
+        u64::from(self.units) * u64::from(self.attack_damage)
     }
 
-    fn damage_multiplier(&self, attack_type: AttackType) -> u32 {
-        if self.weaknesses.contains(&attack_type) {
+    fn damage_multiplier(&self, attack_type: &str) -> u64 {
+        if self
+            .weaknesses
+            .iter()
+            .any(|weakness| weakness == attack_type)
+        {
             2
-        } else if self.immunities.contains(&attack_type) {
+        } else if self
+            .immunities
+            .iter()
+            .any(|immunity| immunity == attack_type)
+        {
             0
         } else {
             1
@@ -42,182 +47,504 @@ impl Group {
     }
 }
 
+// An army is named by its section header in the input. Armies on the same
+// team never attack each other; by default each army is a team of its own.
+#[derive(Debug, Clone)]
+struct Army {
+    name: String,
+    team: String,
+}
+
+// A win names the winning team, along with the units it has left
 #[derive(Debug, Eq, PartialEq)]
-enum GroupType {
-    ImmuneSystem,
-    Infection,
+enum BattleOutcome {
+    Win(String, u32),
+    Stalemate,
+}
+
+#[derive(Debug)]
+struct Selection {
+    attacker: u32,
+    target: u32,
+    damage: u64,
+}
+
+#[derive(Debug)]
+struct Attack {
+    attacker: u32,
+    target: u32,
+    damage: u64,
+    units_killed: u32,
 }
 
 #[derive(Debug)]
+struct GroupStatus {
+    group: u32,
+    army: String,
+    units: u32,
+    effective_power: u64,
+}
+
+// The state of each group at the start of a round, the targets selected,
+// and the attacks made in initiative order
+#[derive(Debug)]
+struct Round {
+    groups: Vec<GroupStatus>,
+    selections: Vec<Selection>,
+    attacks: Vec<Attack>,
+}
+
+impl Round {
+    fn units_killed(&self) -> u32 {
+        self.attacks.iter().map(|attack| attack.units_killed).sum()
+    }
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn escape_json(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[derive(Debug, Default)]
+struct BattleReport {
+    rounds: Vec<Round>,
+}
+
+impl BattleReport {
+    fn groups(&self) -> Vec<u32> {
+        match self.rounds.first() {
+            Some(round) => round.groups.iter().map(|status| status.group).collect(),
+            None => vec![],
+        }
+    }
+
+    // One line per round, with a column for the effective power of each group
+    fn effective_power_table(&self) -> String {
+        let groups = self.groups();
+        let mut table = String::new();
+
+        write!(table, "{:>6}", "Round").unwrap();
+        for group in groups.iter() {
+            write!(table, " {:>9}", group).unwrap();
+        }
+        writeln!(table).unwrap();
+
+        for (j, round) in self.rounds.iter().enumerate() {
+            write!(table, "{:>6}", j + 1).unwrap();
+            for group in groups.iter() {
+                let effective_power = round
+                    .groups
+                    .iter()
+                    .find(|status| status.group == *group)
+                    .map_or(0, |status| status.effective_power);
+                write!(table, " {:>9}", effective_power).unwrap();
+            }
+            writeln!(table).unwrap();
+        }
+
+        table
+    }
+
+    // One row for each group in each round, with the attack it made if any
+    fn to_csv(&self) -> String {
+        let mut csv = String::new();
+
+        writeln!(
+            csv,
+            "round,group,army,units,effective_power,target,damage,units_killed"
+        )
+        .unwrap();
+        for (j, round) in self.rounds.iter().enumerate() {
+            for status in round.groups.iter() {
+                write!(
+                    csv,
+                    "{},{},{},{},{}",
+                    j + 1,
+                    status.group,
+                    escape_csv(&status.army),
+                    status.units,
+                    status.effective_power
+                )
+                .unwrap();
+                match round
+                    .attacks
+                    .iter()
+                    .find(|attack| attack.attacker == status.group)
+                {
+                    Some(attack) => writeln!(
+                        csv,
+                        ",{},{},{}",
+                        attack.target, attack.damage, attack.units_killed
+                    )
+                    .unwrap(),
+                    None => writeln!(csv, ",,,").unwrap(),
+                }
+            }
+        }
+
+        csv
+    }
+
+    fn to_json(&self) -> String {
+        let mut json = String::new();
+
+        json.push_str("{\"rounds\":[");
+        for (j, round) in self.rounds.iter().enumerate() {
+            if j > 0 {
+                json.push(',');
+            }
+            write!(json, "{{\"round\":{},\"groups\":[", j + 1).unwrap();
+            for (k, status) in round.groups.iter().enumerate() {
+                if k > 0 {
+                    json.push(',');
+                }
+                write!(
+                    json,
+                    "{{\"group\":{},\"army\":\"{}\",\"units\":{},\"effective_power\":{}}}",
+                    status.group,
+                    escape_json(&status.army),
+                    status.units,
+                    status.effective_power
+                )
+                .unwrap();
+            }
+            json.push_str("],\"selections\":[");
+            for (k, selection) in round.selections.iter().enumerate() {
+                if k > 0 {
+                    json.push(',');
+                }
+                write!(
+                    json,
+                    "{{\"attacker\":{},\"target\":{},\"damage\":{}}}",
+                    selection.attacker, selection.target, selection.damage
+                )
+                .unwrap();
+            }
+            json.push_str("],\"attacks\":[");
+            for (k, attack) in round.attacks.iter().enumerate() {
+                if k > 0 {
+                    json.push(',');
+                }
+                write!(
+                    json,
+                    "{{\"attacker\":{},\"target\":{},\"damage\":{},\"units_killed\":{}}}",
+                    attack.attacker, attack.target, attack.damage, attack.units_killed
+                )
+                .unwrap();
+            }
+            json.push_str("]}");
+        }
+        json.push_str("]}");
+
+        json
+    }
+}
+
+#[derive(Debug, Clone)]
 struct Engine {
-    groups: HashMap<u32, (GroupType, Group)>,
+    armies: Vec<Army>,
+    groups: HashMap<u32, (usize, Group)>,
 }
 
 impl Engine {
-    fn new(immune_system: Vec<Group>, infection: Vec<Group>) -> Engine {
+    fn new(armies: Vec<(String, Vec<Group>)>) -> Engine {
         let mut groups = HashMap::new();
 
-        let mut group_id = 100;
-        for group in immune_system {
-            groups.insert(group_id, (GroupType::ImmuneSystem, group));
-            group_id += 1;
+        // Groups are numbered from 100 for the first army, 200 for the
+        // second and so on, unless an army has too many groups for that
+        let mut stride = 100;
+        while armies.iter().any(|(_, army)| army.len() >= stride as usize) {
+            stride *= 10;
         }
 
-        group_id = 200;
-        for group in infection {
-            groups.insert(group_id, (GroupType::Infection, group));
-            group_id += 1;
+        for (army, (_, army_groups)) in armies.iter().enumerate() {
+            let first_group_id = (army as u32 + 1) * stride;
+            for (group_id, group) in (first_group_id..).zip(army_groups.iter()) {
+                groups.insert(group_id, (army, group.clone()));
+            }
         }
 
-        Engine { groups }
+        let armies = armies
+            .into_iter()
+            .map(|(name, _)| Army {
+                team: name.clone(),
+                name,
+            })
+            .collect();
+
+        Engine { armies, groups }
+    }
+
+    fn army(&self, name: &str) -> usize {
+        self.armies
+            .iter()
+            .position(|army| army.name == name)
+            .unwrap_or_else(|| panic!("Unknown army {}", name))
+    }
+
+    fn set_team(&mut self, army: &str, team: &str) {
+        let army = self.army(army);
+        self.armies[army].team = team.to_string();
     }
 
-    fn boost(&mut self, boost: u32) {
+    fn enemies(&self, a: usize, b: usize) -> bool {
+        self.armies[a].team != self.armies[b].team
+    }
+
+    fn boost(&mut self, army: &str, boost: u32) {
+        let army = self.army(army);
         self.groups
             .values_mut()
-            .filter(|(group_type, _)| *group_type == GroupType::ImmuneSystem)
+            .filter(|(group_army, _)| *group_army == army)
             .for_each(|(_, group)| group.attack_damage += boost);
     }
 
-    fn fight(&mut self) -> Option<(GroupType, u32)> {
+    fn fight(&mut self) -> BattleOutcome {
+        self.fight_with_report(&mut BattleReport::default())
+    }
+
+    fn fight_with_report(&mut self, report: &mut BattleReport) -> BattleOutcome {
         loop {
-            // Combat ends once one army has lost all its units
-            let immune_system_units: u32 = self
-                .groups
-                .values()
-                .filter(|(group_type, _)| *group_type == GroupType::ImmuneSystem)
-                .map(|(_, group)| group.units)
-                .sum();
-            let infection_units: u32 = self
-                .groups
-                .values()
-                .filter(|(group_type, _)| *group_type == GroupType::Infection)
-                .map(|(_, group)| group.units)
-                .sum();
-            if immune_system_units == 0 {
-                return Some((GroupType::Infection, infection_units));
+            // Combat ends once only one team has units left
+            let mut team_units: Vec<(&str, u32)> = Vec::new();
+            for (army, group) in self.groups.values().filter(|(_, group)| group.units > 0) {
+                let team = self.armies[*army].team.as_str();
+                match team_units.iter_mut().find(|(name, _)| *name == team) {
+                    Some((_, units)) => *units += group.units,
+                    None => team_units.push((team, group.units)),
+                }
+            }
+            match team_units.len() {
+                0 => return BattleOutcome::Stalemate,
+                1 => return BattleOutcome::Win(team_units[0].0.to_string(), team_units[0].1),
+                _ => (),
+            }
+
+            let round = self.round();
+            let units_killed = round.units_killed();
+            report.rounds.push(round);
+
+            // Nothing changes from one round to the next once a round passes
+            // without any units lost, whether or not targets were selected
+            if units_killed == 0 {
+                return BattleOutcome::Stalemate;
+            }
+        }
+    }
+
+    // Find the smallest boost for the army with which its team wins,
+    // returning it along with the units the team has left.
+    //
+    // Outcomes are not monotone in the boost: the team can win with one
+    // boost and then stalemate or lose with a larger one, as target
+    // selection shifts. Doubling the boost finds one with which another
+    // team no longer wins, and every boost is then tried in turn from the
+    // smallest, through any stalemates beyond it.
+    //
+    // Once a single boosted unit can wipe out any group, further boosts
+    // change nothing, so None is returned if another team still wins then.
+    // Once every boosted unit kills at least one unit of any group it
+    // attacks, a stalemate means the team has nothing left it can hurt, so
+    // stepping through stalemates stops there, or after a fixed number of
+    // boosts at most.
+    fn smallest_winning_boost(&self, army: &str) -> Option<(u32, u32)> {
+        const STALEMATE_STEPS: u32 = 1000;
+
+        let limit = self
+            .groups
+            .values()
+            .map(|(_, group)| u64::from(group.units) * u64::from(group.hit_points))
+            .max()
+            .unwrap_or(0)
+            .min(u64::from(u32::MAX / 2)) as u32;
+        let army_index = self.army(army);
+        let team = &self.armies[army_index].team;
+        let strongest_enemy = self
+            .groups
+            .values()
+            .filter(|(group_army, _)| self.armies[*group_army].team != *team)
+            .map(|(_, group)| group.hit_points)
+            .max()
+            .unwrap_or(0);
+        let weakest_attack = self
+            .groups
+            .values()
+            .filter(|(group_army, _)| *group_army == army_index)
+            .map(|(_, group)| group.attack_damage)
+            .min()
+            .unwrap_or(0);
+        let lethal = strongest_enemy.saturating_sub(weakest_attack);
+        let outcome = |boost: u32| {
+            let mut engine = self.clone();
+            engine.boost(army, boost);
+            engine.fight()
+        };
+        let other_team_wins = |boost: u32| match outcome(boost) {
+            BattleOutcome::Win(winner, _) => winner != *team,
+            BattleOutcome::Stalemate => false,
+        };
+
+        let mut high = 1;
+        while other_team_wins(high) {
+            if high >= limit {
+                return None;
             }
-            if infection_units == 0 {
-                return Some((GroupType::ImmuneSystem, immune_system_units));
+            high = (high * 2).min(limit);
+        }
+
+        for boost in 1..high + STALEMATE_STEPS {
+            match outcome(boost) {
+                BattleOutcome::Win(winner, units) if winner == *team => {
+                    return Some((boost, units))
+                }
+                BattleOutcome::Stalemate if boost >= lethal => return None,
+                _ => (),
             }
+        }
 
-            // Target selection phase
+        None
+    }
 
-            // Determine attacker order
-            let mut attacker_keys: Vec<u32> = self
+    fn round(&mut self) -> Round {
+        let mut groups: Vec<GroupStatus> = self
+            .groups
+            .iter()
+            .filter(|(_, (_, group))| group.units > 0)
+            .map(|(key, (army, group))| GroupStatus {
+                group: *key,
+                army: self.armies[*army].name.clone(),
+                units: group.units,
+                effective_power: group.effective_power(),
+            })
+            .collect();
+        groups.sort_by_key(|status| status.group);
+
+        // Target selection phase
+
+        // Determine attacker order
+        let mut attacker_keys: Vec<u32> = self
+            .groups
+            .keys()
+            .filter(|key| self.groups[key].1.units > 0)
+            .cloned()
+            .collect();
+        attacker_keys.sort_by(|a, b| {
+            match self.groups[b]
+                .1
+                .effective_power()
+                .cmp(&self.groups[a].1.effective_power())
+            {
+                Ordering::Less => Ordering::Less,
+                Ordering::Equal => self.groups[b]
+                    .1
+                    .initiative
+                    .cmp(&self.groups[a].1.initiative),
+                Ordering::Greater => Ordering::Greater,
+            }
+        });
+
+        // Determine target for each attacker
+        let mut target_attackers: HashMap<u32, u32> = HashMap::new();
+        let mut selections = Vec::new();
+        for attacker_key in attacker_keys.iter() {
+            let (attacker_army, attacker) = &self.groups[attacker_key];
+            let mut candidates: Vec<Reverse<(u64, u64, u32, u32)>> = self
                 .groups
-                .keys()
-                .filter(|key| self.groups[key].1.units > 0)
-                .cloned()
+                .iter()
+                .filter(|(_, (army, _))| self.enemies(*army, *attacker_army))
+                .filter(|(_, (_, group))| group.units > 0)
+                .map(|(key, (_, group))| {
+                    Reverse((
+                        group.damage_multiplier(&attacker.attack_type),
+                        group.effective_power(),
+                        group.initiative,
+                        *key,
+                    ))
+                })
                 .collect();
-            attacker_keys.sort_by(|a, b| {
-                match self.groups[b]
-                    .1
-                    .effective_power()
-                    .cmp(&self.groups[a].1.effective_power())
-                {
-                    Ordering::Less => Ordering::Less,
-                    Ordering::Equal => self.groups[b]
-                        .1
-                        .initiative
-                        .cmp(&self.groups[a].1.initiative),
-                    Ordering::Greater => Ordering::Greater,
+            candidates.sort();
+
+            for Reverse(candidate) in candidates.iter() {
+                // If the attacker cannot deal any damage, it does not select a target
+                if candidate.0 == 0 {
+                    break;
                 }
-            });
 
-            // Determine target for each attacker
-            let mut target_attackers: HashMap<u32, u32> = HashMap::new();
-            for attacker_key in attacker_keys.iter() {
-                let (attacker_group_type, attacker) = &self.groups[attacker_key];
-                let mut selections: Vec<Reverse<(u32, u32, u32, u32)>> = self
-                    .groups
-                    .iter()
-                    .filter(|(_, (group_type, _))| *group_type != *attacker_group_type)
-                    .filter(|(_, (_, group))| group.units > 0)
-                    .map(|(key, (_, group))| {
-                        Reverse((
-                            group.damage_multiplier(attacker.attack_type),
-                            group.effective_power(),
-                            group.initiative,
-                            *key,
-                        ))
-                    })
-                    .collect();
-                selections.sort();
-
-                for Reverse(selection) in selections.iter() {
-                    // If the attacker cannot deal any damage, it does not select a target
-                    if selection.0 == 0 {
+                match target_attackers.entry(candidate.3) {
+                    Entry::Vacant(v) => {
+                        v.insert(*attacker_key);
+                        selections.push(Selection {
+                            attacker: *attacker_key,
+                            target: candidate.3,
+                            damage: attacker.effective_power() * candidate.0,
+                        });
                         break;
                     }
-
-                    match target_attackers.entry(selection.3) {
-                        Entry::Vacant(v) => {
-                            v.insert(*attacker_key);
-                            break;
-                        }
-                        Entry::Occupied(_) => {
-                            // A group can be the target for only one attacker
-                            continue;
-                        }
+                    Entry::Occupied(_) => {
+                        // A group can be the target for only one attacker
+                        continue;
                     }
                 }
             }
+        }
 
-            // The fight cannot be resolved if no targets can be selected
-            if target_attackers.is_empty() {
-                return None;
+        // Make HashMap to look up target for each attacker
+        let mut attacker_targets: HashMap<u32, u32> = HashMap::new();
+        for (target, attacker) in target_attackers.iter() {
+            if let Some(existing_target) = attacker_targets.insert(*attacker, *target) {
+                panic!("existing target {:?}", existing_target);
             }
+        }
 
-            // Make HashMap to look up target for each attacker
-            let mut attacker_targets: HashMap<u32, u32> = HashMap::new();
-            for (target, attacker) in target_attackers.iter() {
-                if let Some(existing_target) = attacker_targets.insert(*attacker, *target) {
-                    panic!("existing target {:?}", existing_target);
-                }
-            }
+        // Attacking Phase
 
-            // Attacking Phase
+        // Groups attack in decreasing order of initiative
+        attacker_keys.sort_by(|a, b| {
+            self.groups[b]
+                .1
+                .initiative
+                .cmp(&self.groups[a].1.initiative)
+        });
 
-            // Groups attack in decreasing order of initiative
-            attacker_keys.sort_by(|a, b| {
-                self.groups[b]
-                    .1
-                    .initiative
-                    .cmp(&self.groups[a].1.initiative)
-            });
-
-            let mut damage_done = false;
-
-            for attacker_key in attacker_keys.iter() {
-                let (effective_power, attack_type) =
-                    if let Some((_, attacker)) = self.groups.get(&attacker_key) {
-                        if attacker.units == 0 {
-                            continue;
-                        }
-                        (attacker.effective_power(), attacker.attack_type)
-                    } else {
+        let mut attacks = Vec::new();
+
+        for attacker_key in attacker_keys.iter() {
+            let (effective_power, attack_type) =
+                if let Some((_, attacker)) = self.groups.get(attacker_key) {
+                    if attacker.units == 0 {
                         continue;
-                    };
-                if let Some(target_key) = attacker_targets.get(&attacker_key) {
-                    if let Some((_, target)) = self.groups.get_mut(&target_key) {
-                        let damage = effective_power * target.damage_multiplier(attack_type);
-                        let units_killed = damage / target.hit_points;
-                        if units_killed > 0 {
-                            damage_done = true;
-                        }
-                        if units_killed > target.units {
-                            target.units = 0;
-                        } else {
-                            target.units -= units_killed;
-                        }
                     }
+                    (attacker.effective_power(), attacker.attack_type.clone())
+                } else {
+                    continue;
+                };
+            if let Some(target_key) = attacker_targets.get(attacker_key) {
+                if let Some((_, target)) = self.groups.get_mut(target_key) {
+                    let damage = effective_power * target.damage_multiplier(&attack_type);
+                    let units_killed =
+                        (damage / u64::from(target.hit_points)).min(u64::from(target.units)) as u32;
+                    target.units -= units_killed;
+                    attacks.push(Attack {
+                        attacker: *attacker_key,
+                        target: *target_key,
+                        damage,
+                        units_killed,
+                    });
                 }
             }
+        }
 
-            if !damage_done {
-                return None;
-            }
+        Round {
+            groups,
+            selections,
+            attacks,
         }
     }
 }
@@ -231,7 +558,7 @@ fn number<'a>() -> Parser<'a, u8, u32> {
     number
         .collect()
         .convert(str::from_utf8)
-        .convert(|s| u32::from_str_radix(s, 10))
+        .convert(|s| s.parse::<u32>())
 }
 
 fn units<'a>() -> Parser<'a, u8, u32> {
@@ -243,13 +570,11 @@ fn hit_points<'a>() -> Parser<'a, u8, u32> {
 }
 
 fn attack_type<'a>() -> Parser<'a, u8, AttackType> {
-    use AttackType::*;
-
-    seq(b"bludgeoning").map(|_| Bludgeoning)
-        | seq(b"cold").map(|_| Cold)
-        | seq(b"fire").map(|_| Fire)
-        | seq(b"radiation").map(|_| Radiation)
-        | seq(b"slashing").map(|_| Slashing)
+    is_a(|c: u8| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+        .repeat(1..)
+        .collect()
+        .convert(str::from_utf8)
+        .map(String::from)
 }
 
 fn attack_types<'a>() -> Parser<'a, u8, Vec<AttackType>> {
@@ -315,45 +640,322 @@ fn group<'a>() -> Parser<'a, u8, Group> {
         )
 }
 
-fn immune_system<'a>() -> Parser<'a, u8, Vec<Group>> {
-    seq(b"Immune System:") * (space() * group()).repeat(1..)
-}
+// Each army is a section headed by its name, such as "Immune System:"
+fn army<'a>() -> Parser<'a, u8, (String, Vec<Group>)> {
+    let name = none_of(b":\r\n")
+        .repeat(1..)
+        .collect()
+        .convert(str::from_utf8)
+        .map(|name| name.trim().to_string());
 
-fn infection<'a>() -> Parser<'a, u8, Vec<Group>> {
-    space() * seq(b"Infection:") * (space() * group()).repeat(1..)
+    (space() * name - sym(b':')) + (space() * group()).repeat(1..)
 }
 
 fn engine<'a>() -> Parser<'a, u8, Engine> {
-    (immune_system() + infection())
-        .map(|(immune_system, infection)| Engine::new(immune_system, infection))
+    army().repeat(1..).map(Engine::new)
 }
 
 fn main() -> Result<(), Error> {
     let mut input = String::new();
     stdin().read_to_string(&mut input).unwrap();
 
+    // Armies can be allied with --team ARMY=TEAM, and the army to boost
+    // (the first one by default) chosen with --army. Optionally trace a
+    // battle with that army boosted by --boost, printing the effective
+    // power of each group over time with --summary and writing the full
+    // report with --json or --csv.
+    let mut teams = Vec::new();
+    let mut boosted_army = None;
+    let mut trace_boost = None;
+    let mut summary = false;
+    let mut json_path = None;
+    let mut csv_path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--team" => {
+                let team = args.next().expect("--team requires ARMY=TEAM");
+                let mut parts = team.splitn(2, '=');
+                let army = parts.next().unwrap().to_string();
+                let team = parts.next().expect("--team requires ARMY=TEAM").to_string();
+                teams.push((army, team));
+            }
+            "--army" => boosted_army = args.next(),
+            "--boost" => trace_boost = args.next().map(|x| x.parse::<u32>().unwrap()),
+            "--summary" => summary = true,
+            "--json" => json_path = args.next(),
+            "--csv" => csv_path = args.next(),
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+
     let mut engine_p1 = engine().parse(input.as_bytes())?;
+    for (army, team) in teams.iter() {
+        engine_p1.set_team(army, team);
+    }
+    let boosted_army = boosted_army.unwrap_or_else(|| engine_p1.armies[0].name.clone());
+
+    let engine_p2 = engine_p1.clone();
+    let engine_trace = engine_p1.clone();
+
+    match engine_p1.fight() {
+        BattleOutcome::Win(winning_team, winning_army_units) => println!(
+            "Part 1: the winning army ({}) has {} units",
+            winning_team, winning_army_units
+        ),
+        BattleOutcome::Stalemate => println!("Part 1: the battle ends in a stalemate"),
+    }
+
+    let team = &engine_p2.armies[engine_p2.army(&boosted_army)].team;
+    match engine_p2.smallest_winning_boost(&boosted_army) {
+        Some((boost, winning_army_units)) => println!(
+            "Part 2: with a boost of {}, the {} has {} units left",
+            boost, team, winning_army_units
+        ),
+        None => println!("Part 2: no boost lets the {} win", team),
+    }
+
+    if summary || json_path.is_some() || csv_path.is_some() {
+        let mut engine = engine_trace;
+        let boost = trace_boost.unwrap_or(0);
+        engine.boost(&boosted_army, boost);
+        let mut report = BattleReport::default();
+        let outcome = engine.fight_with_report(&mut report);
+        println!(
+            "With a boost of {}, the battle lasts {} rounds: {:?}",
+            boost,
+            report.rounds.len(),
+            outcome
+        );
+        if summary {
+            print!("{}", report.effective_power_table());
+        }
+        if let Some(path) = json_path {
+            fs::write(path, report.to_json()).expect("Unable to write JSON report");
+        }
+        if let Some(path) = csv_path {
+            fs::write(path, report.to_csv()).expect("Unable to write CSV report");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "Immune System:
+17 units each with 5390 hit points (weak to radiation, bludgeoning) with an attack that does 4507 fire damage at initiative 2
+989 units each with 1274 hit points (immune to fire; weak to bludgeoning, slashing) with an attack that does 25 slashing damage at initiative 3
+
+Infection:
+801 units each with 4706 hit points (weak to radiation) with an attack that does 116 bludgeoning damage at initiative 1
+4485 units each with 2961 hit points (immune to radiation; weak to fire, cold) with an attack that does 12 slashing damage at initiative 4
+";
+
+    #[test]
+    fn test_fight() {
+        let mut engine = engine().parse(EXAMPLE.as_bytes()).unwrap();
+        assert_eq!(
+            engine.fight(),
+            BattleOutcome::Win(String::from("Infection"), 5216)
+        );
+    }
+
+    #[test]
+    fn test_fight_boosted() {
+        let mut engine = engine().parse(EXAMPLE.as_bytes()).unwrap();
+        engine.boost("Immune System", 1570);
+        assert_eq!(
+            engine.fight(),
+            BattleOutcome::Win(String::from("Immune System"), 51)
+        );
+    }
 
-    if let Some((_, winning_army_units)) = engine_p1.fight() {
-        println!("Part 1: the winning army has {} units", winning_army_units);
-    }
-
-    let mut boost = 1;
-    loop {
-        let mut engine_p2 = engine().parse(input.as_bytes())?;
-        engine_p2.boost(boost);
-        if let Some((winning_army_group_type, winning_army_units)) = engine_p2.fight() {
-            if winning_army_group_type == GroupType::ImmuneSystem {
-                println!(
-                    "Part 2: the immune system has {} units left",
-                    winning_army_units
-                );
-                break;
+    #[test]
+    fn test_smallest_winning_boost() {
+        let engine = engine().parse(EXAMPLE.as_bytes()).unwrap();
+        assert_eq!(
+            engine.smallest_winning_boost("Immune System"),
+            Some((1570, 51))
+        );
+    }
+
+    #[test]
+    fn test_win_before_losses() {
+        // The Immune System wins with a boost of 51, loses with 52 to 54 and
+        // wins again from 55
+        let input = "Immune System:
+12 units each with 29 hit points (weak to slashing) with an attack that does 12 slashing damage at initiative 1
+26 units each with 56 hit points (weak to fire; immune to slashing) with an attack that does 14 cold damage at initiative 2
+
+Infection:
+14 units each with 58 hit points (weak to slashing) with an attack that does 18 fire damage at initiative 3
+24 units each with 58 hit points (weak to slashing; immune to fire) with an attack that does 19 slashing damage at initiative 4
+";
+        let engine = engine().parse(input.as_bytes()).unwrap();
+        for (boost, winner) in [
+            (51, "Immune System"),
+            (52, "Infection"),
+            (55, "Immune System"),
+        ]
+        .iter()
+        {
+            let mut boosted = engine.clone();
+            boosted.boost("Immune System", *boost);
+            match boosted.fight() {
+                BattleOutcome::Win(team, _) => assert_eq!(team, *winner, "boost {}", boost),
+                outcome => panic!("boost {}: {:?}", boost, outcome),
             }
         }
+        assert_eq!(
+            engine.smallest_winning_boost("Immune System"),
+            Some((51, 5))
+        );
+    }
 
-        boost += 1;
+    #[test]
+    fn test_no_winning_boost() {
+        // The Infection can hurt the Immune System but not the other way
+        // round, so no boost helps
+        let input = "Immune System:
+10 units each with 10 hit points with an attack that does 5 cold damage at initiative 2
+
+Infection:
+10 units each with 10 hit points (immune to cold) with an attack that does 5 fire damage at initiative 1
+";
+        let one_sided = engine().parse(input.as_bytes()).unwrap();
+        assert_eq!(one_sided.smallest_winning_boost("Immune System"), None);
+
+        // Neither side can hurt the other, whatever the boost
+        let input = "Immune System:
+10 units each with 10 hit points (immune to fire) with an attack that does 5 cold damage at initiative 2
+
+Infection:
+10 units each with 10 hit points (immune to cold) with an attack that does 5 fire damage at initiative 1
+";
+        let immune = engine().parse(input.as_bytes()).unwrap();
+        assert_eq!(immune.smallest_winning_boost("Immune System"), None);
+
+        // Large groups must not mean a battle for every possible boost
+        let input = "Immune System:
+5000 units each with 50000 hit points (immune to fire) with an attack that does 5 cold damage at initiative 2
+
+Infection:
+5000 units each with 50000 hit points (immune to cold) with an attack that does 5 fire damage at initiative 1
+";
+        let large = engine().parse(input.as_bytes()).unwrap();
+        assert_eq!(large.smallest_winning_boost("Immune System"), None);
     }
 
-    Ok(())
+    const SKIRMISH: &str = "Knights:
+10 units each with 10 hit points (weak to acid) with an attack that does 5 steel damage at initiative 3
+
+Trolls:
+10 units each with 10 hit points (immune to steel) with an attack that does 5 acid damage at initiative 2
+
+Dragons:
+2 units each with 100 hit points (weak to steel) with an attack that does 50 fire damage at initiative 1
+";
+
+    #[test]
+    fn test_free_for_all() {
+        let mut engine = engine().parse(SKIRMISH.as_bytes()).unwrap();
+        assert_eq!(engine.armies.len(), 3);
+        assert_eq!(engine.groups[&300].1.attack_type, "fire");
+        assert_eq!(engine.groups[&100].1.damage_multiplier("acid"), 2);
+        assert_eq!(engine.smallest_winning_boost("Knights"), None);
+        assert_eq!(
+            engine.fight(),
+            BattleOutcome::Win(String::from("Dragons"), 1)
+        );
+    }
+
+    #[test]
+    fn test_teams() {
+        let mut engine = engine().parse(SKIRMISH.as_bytes()).unwrap();
+        engine.set_team("Knights", "Alliance");
+        engine.set_team("Trolls", "Alliance");
+        assert_eq!(engine.smallest_winning_boost("Knights"), Some((5, 20)));
+
+        engine.boost("Knights", 5);
+        let mut report = BattleReport::default();
+        let outcome = engine.fight_with_report(&mut report);
+        assert!(report.rounds.iter().all(|round| round
+            .selections
+            .iter()
+            .all(|selection| selection.attacker / 100 == 3 || selection.target / 100 == 3)));
+        assert_eq!(outcome, BattleOutcome::Win(String::from("Alliance"), 20));
+    }
+
+    #[test]
+    fn test_stalemate_without_targets() {
+        let input = "Immune System:
+10 units each with 10 hit points (immune to fire) with an attack that does 5 cold damage at initiative 2
+
+Infection:
+10 units each with 10 hit points (immune to cold) with an attack that does 5 fire damage at initiative 1
+";
+        let mut engine = engine().parse(input.as_bytes()).unwrap();
+        assert_eq!(engine.fight(), BattleOutcome::Stalemate);
+    }
+
+    #[test]
+    fn test_stalemate_without_losses() {
+        let input = "Immune System:
+1 units each with 1000 hit points with an attack that does 5 cold damage at initiative 2
+
+Infection:
+1 units each with 1000 hit points with an attack that does 5 fire damage at initiative 1
+";
+        let mut engine = engine().parse(input.as_bytes()).unwrap();
+        let mut report = BattleReport::default();
+        assert_eq!(
+            engine.fight_with_report(&mut report),
+            BattleOutcome::Stalemate
+        );
+        assert_eq!(report.rounds.len(), 1);
+        assert_eq!(report.rounds[0].selections.len(), 2);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut engine = engine().parse(EXAMPLE.as_bytes()).unwrap();
+        let mut report = BattleReport::default();
+        engine.fight_with_report(&mut report);
+        assert_eq!(report.rounds.len(), 8);
+
+        // The first round of the example in the puzzle description
+        let round = &report.rounds[0];
+        let attacks: Vec<(u32, u32, u32)> = round
+            .attacks
+            .iter()
+            .map(|attack| (attack.attacker, attack.target, attack.units_killed))
+            .collect();
+        assert_eq!(
+            attacks,
+            vec![
+                (201, 101, 84),
+                (101, 200, 4),
+                (100, 201, 51),
+                (200, 100, 17)
+            ]
+        );
+        let selection = round
+            .selections
+            .iter()
+            .find(|selection| selection.attacker == 200)
+            .unwrap();
+        assert_eq!((selection.target, selection.damage), (100, 185832));
+
+        let csv = report.to_csv();
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("1,100,Immune System,17,76619,201,153238,51")
+        );
+        let json = report.to_json();
+        assert!(json.starts_with("{\"rounds\":[{\"round\":1,\"groups\":[{\"group\":100,"));
+        assert_eq!(report.effective_power_table().lines().count(), 9);
+    }
 }
@@ -1,56 +1,224 @@
 use pom::parser::*;
 use pom::Error;
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::env;
 use std::io::{stdin, Read};
 use std::str;
 
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
 struct Point {
-    w: i32,
-    x: i32,
-    y: i32,
-    z: i32,
+    coordinates: Vec<i32>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Metric {
+    Manhattan,
+    Chebyshev,
+    Euclidean,
+}
+
+impl Metric {
+    fn within(self, a: &Point, b: &Point, threshold: i64) -> bool {
+        let deltas = a
+            .coordinates
+            .iter()
+            .zip(b.coordinates.iter())
+            .map(|(a, b)| (i64::from(*a) - i64::from(*b)).abs());
+
+        match self {
+            Metric::Manhattan => deltas.sum::<i64>() <= threshold,
+            Metric::Chebyshev => deltas.max().unwrap_or(0) <= threshold,
+            // Compare squares to stay in integers
+            Metric::Euclidean => {
+                deltas.map(|delta| delta * delta).sum::<i64>() <= threshold * threshold
+            }
+        }
+    }
+}
+
+// A disjoint-set forest with union by rank and path compression
+#[derive(Debug)]
+struct DisjointSet {
+    parents: Vec<usize>,
+    ranks: Vec<u8>,
+}
+
+impl DisjointSet {
+    fn new(size: usize) -> DisjointSet {
+        DisjointSet {
+            parents: (0..size).collect(),
+            ranks: vec![0; size],
+        }
+    }
+
+    fn find(&mut self, element: usize) -> usize {
+        let mut root = element;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+
+        let mut element = element;
+        while self.parents[element] != root {
+            let parent = self.parents[element];
+            self.parents[element] = root;
+            element = parent;
+        }
+
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+
+        if self.ranks[a] < self.ranks[b] {
+            self.parents[a] = b;
+        } else if self.ranks[a] > self.ranks[b] {
+            self.parents[b] = a;
+        } else {
+            self.parents[b] = a;
+            self.ranks[a] += 1;
+        }
+    }
+}
+
+// Buckets the points into a grid of cubic cells, so a radius query only
+// has to look at the cells overlapping the radius around its center. It is
+// built for this day's points and metrics rather than shared with others.
+#[derive(Debug)]
+struct GridIndex<'a> {
+    points: &'a [Point],
+    cell_size: i64,
+    cells: HashMap<Vec<i64>, Vec<usize>>,
+}
+
+impl<'a> GridIndex<'a> {
+    fn new(points: &'a [Point], cell_size: i64) -> GridIndex<'a> {
+        let cell_size = cell_size.max(1);
+        let mut cells: HashMap<Vec<i64>, Vec<usize>> = HashMap::new();
+        for (j, point) in points.iter().enumerate() {
+            cells
+                .entry(GridIndex::cell(point, cell_size))
+                .or_default()
+                .push(j);
+        }
+
+        GridIndex {
+            points,
+            cell_size,
+            cells,
+        }
+    }
+
+    fn cell(point: &Point, cell_size: i64) -> Vec<i64> {
+        point
+            .coordinates
+            .iter()
+            .map(|&x| i64::from(x).div_euclid(cell_size))
+            .collect()
+    }
+
+    // Indices of all the points within radius of the center, in increasing order
+    fn neighbors(&self, center: &Point, radius: i64, metric: Metric) -> Vec<usize> {
+        let mut neighbors = Vec::new();
+        let origin = GridIndex::cell(center, self.cell_size);
+        let span = (radius + self.cell_size - 1) / self.cell_size;
+
+        // In many dimensions there are far more cells around the center than
+        // occupied cells, so look through the occupied ones instead
+        let window = (2 * span as usize + 1).checked_pow(origin.len() as u32);
+        if window.is_none_or(|window| window > self.cells.len()) {
+            for (cell, indices) in self.cells.iter() {
+                if cell.iter().zip(&origin).all(|(c, o)| (c - o).abs() <= span) {
+                    neighbors.extend(
+                        indices
+                            .iter()
+                            .filter(|&&j| metric.within(center, &self.points[j], radius)),
+                    );
+                }
+            }
+            neighbors.sort_unstable();
+            return neighbors;
+        }
+
+        // Step through every cell offset in [-span, span] on each axis
+        let mut offsets = vec![-span; origin.len()];
+        let mut cell = Vec::with_capacity(origin.len());
+        loop {
+            cell.clear();
+            cell.extend(origin.iter().zip(&offsets).map(|(c, o)| c + o));
+            if let Some(indices) = self.cells.get(&cell) {
+                neighbors.extend(
+                    indices
+                        .iter()
+                        .filter(|&&j| metric.within(center, &self.points[j], radius)),
+                );
+            }
+
+            match offsets.iter().position(|&o| o < span) {
+                Some(axis) => {
+                    offsets[axis] += 1;
+                    for offset in offsets[..axis].iter_mut() {
+                        *offset = -span;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        neighbors.sort_unstable();
+        neighbors
+    }
 }
 
 #[derive(Debug)]
 struct Engine {
     points: Vec<Point>,
-    constellations: Vec<HashSet<Point>>,
+    metric: Metric,
+    threshold: i64,
 }
 
 impl Engine {
-    fn run(&mut self) -> u32 {
-        while let Some(point) = self.points.pop() {
-            let mut new_constellations = Vec::new();
-            let mut new_constellation = HashSet::new();
-
-            while let Some(constellation) = self.constellations.pop() {
-                let mut point_is_in_constellation = false;
-                for constellation_point in constellation.iter() {
-                    if (point.w - constellation_point.w).abs()
-                        + (point.x - constellation_point.x).abs()
-                        + (point.y - constellation_point.y).abs()
-                        + (point.z - constellation_point.z).abs()
-                        <= 3
-                    {
-                        point_is_in_constellation = true;
-                        break;
-                    }
-                }
-                if point_is_in_constellation {
-                    new_constellation = new_constellation.union(&constellation).cloned().collect();
-                } else {
-                    new_constellations.push(constellation);
+    fn new(points: Vec<Point>) -> Engine {
+        Engine {
+            points,
+            metric: Metric::Manhattan,
+            threshold: 3,
+        }
+    }
+
+    // Group the points into constellations, each listing the indices of
+    // its points in input order, ordered by their first point
+    fn constellations(&self) -> Vec<Vec<usize>> {
+        let mut disjoint_set = DisjointSet::new(self.points.len());
+
+        let index = GridIndex::new(&self.points, self.threshold);
+        for (j, point) in self.points.iter().enumerate() {
+            for k in index.neighbors(point, self.threshold, self.metric) {
+                if k > j {
+                    disjoint_set.union(j, k);
                 }
             }
+        }
 
-            new_constellation.insert(point);
-            new_constellations.push(new_constellation);
-
-            self.constellations = new_constellations;
+        let mut indices: HashMap<usize, usize> = HashMap::new();
+        let mut constellations: Vec<Vec<usize>> = Vec::new();
+        for j in 0..self.points.len() {
+            let root = disjoint_set.find(j);
+            let constellation = *indices.entry(root).or_insert_with(|| {
+                constellations.push(Vec::new());
+                constellations.len() - 1
+            });
+            constellations[constellation].push(j);
         }
 
-        self.constellations.len() as u32
+        constellations
+    }
+
+    fn run(&self) -> u32 {
+        self.constellations().len() as u32
     }
 }
 
@@ -64,18 +232,28 @@ fn number<'a>() -> Parser<'a, u8, i32> {
     number
         .collect()
         .convert(str::from_utf8)
-        .convert(|s| i32::from_str_radix(s, 10))
+        .convert(|s| s.parse::<i32>())
 }
 
 fn point<'a>() -> Parser<'a, u8, Point> {
-    (number() + ((sym(b',') * number()) + ((sym(b',') * number()) + sym(b',') * number())))
-        .map(|(w, (x, (y, z)))| Point { w, x, y, z })
+    (number() + (sym(b',') * number()).repeat(0..)).map(|(first, rest)| {
+        let mut coordinates = vec![first];
+        coordinates.extend(rest);
+        Point { coordinates }
+    })
 }
 
 fn engine<'a>() -> Parser<'a, u8, Engine> {
-    (space() * point()).repeat(1..).map(|points| Engine {
-        points,
-        constellations: vec![],
+    (space() * point()).repeat(1..).convert(|points| {
+        let dimension = points[0].coordinates.len();
+        if points
+            .iter()
+            .all(|point| point.coordinates.len() == dimension)
+        {
+            Ok(Engine::new(points))
+        } else {
+            Err("All points must have the same number of coordinates")
+        }
     })
 }
 
@@ -85,8 +263,219 @@ fn main() -> Result<(), Error> {
 
     let mut engine = engine().parse(input.as_bytes())?;
 
+    // The metric and threshold can be changed with --metric and --threshold,
+    // and --members lists the points in each constellation
+    let mut members = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--metric" => {
+                engine.metric = match args.next().as_deref() {
+                    Some("manhattan") => Metric::Manhattan,
+                    Some("chebyshev") => Metric::Chebyshev,
+                    Some("euclidean") => Metric::Euclidean,
+                    _ => panic!("--metric requires manhattan, chebyshev or euclidean"),
+                }
+            }
+            "--threshold" => {
+                engine.threshold = args
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .expect("--threshold requires a number")
+            }
+            "--members" => members = true,
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+
+    if members {
+        for (j, constellation) in engine.constellations().iter().enumerate() {
+            let points: Vec<String> = constellation
+                .iter()
+                .map(|&k| {
+                    let coordinates: Vec<String> = engine.points[k]
+                        .coordinates
+                        .iter()
+                        .map(|x| x.to_string())
+                        .collect();
+                    coordinates.join(",")
+                })
+                .collect();
+            println!("Constellation {}: {}", j + 1, points.join(" "));
+        }
+    }
+
     let constellation_count = engine.run();
     println!("Part 1: {} constellations are formed", constellation_count);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constellation_count(input: &str) -> u32 {
+        engine().parse(input.as_bytes()).unwrap().run()
+    }
+
+    struct Generator {
+        seed: u64,
+    }
+
+    impl Generator {
+        fn next(&mut self, low: i32, high: i32) -> i32 {
+            self.seed = self
+                .seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            low + ((self.seed >> 33) % (high - low + 1) as u64) as i32
+        }
+
+        fn points(&mut self, count: usize, dimension: usize, limit: i32) -> Vec<Point> {
+            (0..count)
+                .map(|_| Point {
+                    coordinates: (0..dimension).map(|_| self.next(-limit, limit)).collect(),
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_example_1() {
+        let input = "0,0,0,0 3,0,0,0 0,3,0,0 0,0,3,0 0,0,0,3 0,0,0,6 9,0,0,0 12,0,0,0";
+        assert_eq!(constellation_count(input), 2);
+    }
+
+    #[test]
+    fn test_example_2() {
+        let input = "-1,2,2,0 0,0,2,-2 0,0,0,-2 -1,2,0,0 -2,-2,-2,2
+            3,0,2,-1 -1,3,2,2 -1,0,-1,0 0,2,1,-2 3,0,0,0";
+        assert_eq!(constellation_count(input), 4);
+    }
+
+    #[test]
+    fn test_example_3() {
+        let input = "1,-1,0,1 2,0,-1,0 3,2,-1,0 0,0,3,1 0,0,-1,-1
+            2,3,-2,0 -2,2,0,0 2,-2,0,-1 1,-1,0,-1 3,2,0,2";
+        assert_eq!(constellation_count(input), 3);
+    }
+
+    #[test]
+    fn test_example_4() {
+        let input = "1,-1,-1,-2 -2,-2,0,1 0,2,1,3 -2,3,-2,1 0,2,3,-2
+            -1,-1,1,-2 0,-2,-1,0 -2,2,3,-1 1,2,2,0 -1,-2,0,-2";
+        assert_eq!(constellation_count(input), 8);
+    }
+
+    #[test]
+    fn test_members() {
+        let input = "0,0,0,0 3,0,0,0 0,3,0,0 0,0,3,0 0,0,0,3 0,0,0,6 9,0,0,0 12,0,0,0";
+        let engine = engine().parse(input.as_bytes()).unwrap();
+        assert_eq!(
+            engine.constellations(),
+            vec![vec![0, 1, 2, 3, 4, 5], vec![6, 7]]
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut engine = engine().parse("0,0 2,2 4,4 9,0".as_bytes()).unwrap();
+        engine.threshold = 2;
+        assert_eq!(engine.run(), 4);
+        engine.metric = Metric::Chebyshev;
+        assert_eq!(engine.run(), 2);
+        engine.threshold = 3;
+        engine.metric = Metric::Euclidean;
+        assert_eq!(engine.run(), 2);
+    }
+
+    // Flood fill over all pairs
+    fn pairwise_count(engine: &Engine) -> u32 {
+        let mut count = 0;
+        let mut seen = vec![false; engine.points.len()];
+        for start in 0..engine.points.len() {
+            if seen[start] {
+                continue;
+            }
+            count += 1;
+            seen[start] = true;
+            let mut stack = vec![start];
+            while let Some(j) = stack.pop() {
+                for (k, b) in engine.points.iter().enumerate() {
+                    if !seen[k] && engine.metric.within(&engine.points[j], b, engine.threshold) {
+                        seen[k] = true;
+                        stack.push(k);
+                    }
+                }
+            }
+        }
+
+        count
+    }
+
+    #[test]
+    fn test_against_pairwise() {
+        let mut generator = Generator { seed: 2018 };
+
+        for &metric in [Metric::Manhattan, Metric::Chebyshev, Metric::Euclidean].iter() {
+            for _ in 0..20 {
+                let mut engine = Engine::new(generator.points(200, 3, 12));
+                engine.metric = metric;
+                engine.threshold = i64::from(generator.next(1, 4));
+                assert_eq!(engine.run(), pairwise_count(&engine));
+            }
+        }
+    }
+
+    #[test]
+    fn test_many_dimensions() {
+        // Each point has 3^12 cells around it, far more than there are points
+        let mut generator = Generator { seed: 12 };
+        let engine = Engine::new(generator.points(500, 12, 1));
+        assert_eq!(engine.run(), pairwise_count(&engine));
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let mut generator = Generator { seed: 34 };
+        let points = generator.points(500, 3, 20);
+
+        for &cell_size in [1, 3, 7].iter() {
+            let index = GridIndex::new(&points, cell_size);
+            for &metric in [Metric::Manhattan, Metric::Chebyshev, Metric::Euclidean].iter() {
+                for _ in 0..20 {
+                    let center = generator.points(1, 3, 25).remove(0);
+                    let radius = i64::from(generator.next(0, 10));
+                    let expected: Vec<usize> = (0..points.len())
+                        .filter(|&j| metric.within(&center, &points[j], radius))
+                        .collect();
+                    assert_eq!(index.neighbors(&center, radius, metric), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mixed_dimensions() {
+        assert!(engine().parse("0,0,0 1,1".as_bytes()).is_err());
+    }
+
+    // Times grouping 100000 four-dimensional points into constellations
+    #[test]
+    #[ignore]
+    fn bench_constellations() {
+        use std::time::Instant;
+
+        let mut generator = Generator { seed: 25 };
+        let engine = Engine::new(generator.points(100_000, 4, 40));
+
+        let start = Instant::now();
+        let constellation_count = engine.run();
+        println!(
+            "{} constellations in {:?}",
+            constellation_count,
+            start.elapsed()
+        );
+    }
+}